@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+
+use crate::config::CONFIG;
+use crate::dns;
+use crate::rest::{NewRecord, Rest, Zone};
+use crate::Error;
+
+const PREFIX: &str = "_acme-challenge";
+
+// `example.com`, `*.example.com` and `_acme-challenge.example.com.` all
+// name the same challenge record
+fn challenge(domain: &str) -> String {
+    let domain = domain
+        .trim()
+        .trim_end_matches('.')
+        .trim_start_matches("*.")
+        .to_lowercase();
+    if domain.starts_with(&format!("{PREFIX}.")) {
+        domain
+    } else {
+        format!("{PREFIX}.{domain}")
+    }
+}
+
+// the most specific zone the record belongs to, and the name relative to it
+fn zone_for(rest: &Rest, fqdn: &str) -> Result<(Zone, String), Error> {
+    let zone = rest
+        .zones()?
+        .into_iter()
+        .filter(|zone| fqdn.ends_with(&format!(".{}", zone.name)))
+        .max_by_key(|zone| zone.name.len())
+        .ok_or_else(|| Error::Other(format!("no zone found for {fqdn}")))?;
+    let name = fqdn[..fqdn.len() - zone.name.len() - 1].to_string();
+    Ok((zone, name))
+}
+
+fn is_token(data: &str, token: &str) -> bool {
+    data.trim_matches('"') == token
+}
+
+pub fn present(rest: &Rest, domain: &str, token: &str) -> Result<(), Error> {
+    let fqdn = challenge(domain);
+    let (zone, name) = zone_for(rest, &fqdn)?;
+    let exists = rest
+        .records(zone.id)?
+        .iter()
+        .any(|r| r.kind == "TXT" && r.name == name && is_token(&r.data, token));
+    if exists {
+        info!("TXT {fqdn} is already set");
+    } else {
+        let record = NewRecord {
+            kind: "TXT",
+            name: &name,
+            data: token,
+            priority: None,
+        };
+        rest.create_record(zone.id, &record)?;
+        info!("created TXT {fqdn}");
+    }
+    wait(&zone.name, &fqdn, token)
+}
+
+/// Removes the challenge records of `domain`, only the one holding
+/// `token` if it is given.
+pub fn cleanup(rest: &Rest, domain: &str, token: Option<&str>) -> Result<(), Error> {
+    let fqdn = challenge(domain);
+    let (zone, name) = zone_for(rest, &fqdn)?;
+    for record in rest.records(zone.id)? {
+        if record.kind == "TXT"
+            && record.name == name
+            && token.is_none_or(|token| is_token(&record.data, token))
+        {
+            rest.delete_record(zone.id, record.id)?;
+            info!("deleted TXT {fqdn}: {}", record.data);
+        }
+    }
+    Ok(())
+}
+
+// the CA may ask any of the authoritative nameservers, so all of them
+// must serve the token before the challenge can be answered
+fn wait(zone: &str, fqdn: &str, token: &str) -> Result<(), Error> {
+    let timeout = Duration::from_secs_f64(CONFIG.acme.timeout);
+    if timeout.is_zero() {
+        return Ok(());
+    }
+    let mut pending = if CONFIG.acme.nameservers.is_empty() {
+        dns::nameservers(zone)?
+    } else {
+        CONFIG.acme.nameservers.clone()
+    };
+    let start = Instant::now();
+    loop {
+        pending.retain(
+            |server| match dns::query(server, fqdn, dns::TYPE_TXT, false) {
+                Ok(answers) => !answers
+                    .iter()
+                    .any(|a| a.kind == dns::TYPE_TXT && a.data == token),
+                Err(err) => {
+                    debug!("{err}");
+                    true
+                }
+            },
+        );
+        if pending.is_empty() {
+            info!("TXT {fqdn} is served by all nameservers");
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(Error::Other(format!(
+                "TXT {fqdn} not propagated to {} after {:.0}s",
+                pending.join(", "),
+                timeout.as_secs_f64()
+            )));
+        }
+        debug!("waiting for {}", pending.join(", "));
+        std::thread::sleep(Duration::from_secs_f64(CONFIG.acme.interval));
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use crate::config::CONFIG;
+
+pub struct Backoff {
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Backoff {
+            failures: 0,
+            until: None,
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        match self.until {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+
+    // logs the failure, returns false once the pending change should be dropped
+    pub fn retry(&mut self, err: &str) -> bool {
+        match self.fail() {
+            Some(delay) => {
+                error!(
+                    "{err}, retry #{} in {:.1}s",
+                    self.failures,
+                    delay.as_secs_f64()
+                );
+                true
+            }
+            None => {
+                error!("{err}, giving up after {} attempts", CONFIG.retry.attempts);
+                false
+            }
+        }
+    }
+
+    // doubles the delay on every consecutive failure up to `retry.cap`,
+    // returns None once `retry.attempts` (0 = unlimited) is used up
+    fn fail(&mut self) -> Option<Duration> {
+        let retry = &CONFIG.retry;
+        self.failures = self.failures.saturating_add(1);
+        if retry.attempts > 0 && self.failures >= retry.attempts {
+            self.reset();
+            return None;
+        }
+        let exp = 2f64.powi(self.failures.min(32) as i32 - 1);
+        let delay = (retry.base * exp).min(retry.cap);
+        let jitter = retry.jitter.clamp(0.0, 1.0) * (fastrand::f64() * 2.0 - 1.0);
+        let delay = Duration::from_secs_f64((delay * (1.0 + jitter)).max(0.0));
+        self.until = Some(Instant::now() + delay);
+        Some(delay)
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.until = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the defaults: 30s base, 3600s cap, 20% jitter, unlimited attempts
+    #[test]
+    fn delays() {
+        crate::testing::init();
+        let mut backoff = Backoff::new();
+        assert!(backoff.ready());
+        let first = backoff.fail().unwrap().as_secs_f64();
+        assert!((24.0..=36.0).contains(&first), "{first}");
+        assert!(!backoff.ready());
+        let second = backoff.fail().unwrap().as_secs_f64();
+        assert!((48.0..=72.0).contains(&second), "{second}");
+        for _ in 0..40 {
+            let delay = backoff.fail().unwrap().as_secs_f64();
+            assert!(delay <= 3600.0 * 1.2, "{delay}");
+        }
+        assert!(backoff.retry("still failing"));
+        backoff.reset();
+        assert!(backoff.ready());
+    }
+}
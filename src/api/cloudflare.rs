@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{Outcome, Provider};
+use crate::config::CONFIG;
+use crate::requests::RequestBuilder;
+use crate::{Error, CLIENT};
+
+const CLOUDFLARE_URL: &str = "https://api.cloudflare.com/client/v4";
+
+// every response is wrapped like this, errors included
+#[derive(Deserialize)]
+struct Envelope<T> {
+    #[serde(default)]
+    success: bool,
+    #[serde(default)]
+    errors: Vec<Message>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    code: u32,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DnsRecord {
+    id: String,
+    content: String,
+    ttl: u32,
+    #[serde(default)]
+    proxied: bool,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ZoneQuery<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct RecordQuery<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct NewRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    name: &'a str,
+    content: &'a str,
+    ttl: u32,
+    proxied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<&'a str>,
+}
+
+fn call<T: DeserializeOwned>(req: RequestBuilder, body: Option<&NewRecord>) -> Result<T, Error> {
+    let req = req.bearer_auth(&CONFIG.cloudflare.token);
+    let res = match body {
+        Some(body) => req.send_json(body)?,
+        None => req.send()?,
+    };
+    let status = res.status();
+    let text = res.text()?;
+    let envelope: Envelope<T> = match serde_json::from_str(&text) {
+        Ok(envelope) => envelope,
+        Err(_) if !status.is_success() => {
+            return Err(Error::Status(status.as_u16(), text.trim().to_string()))
+        }
+        Err(e) => return Err(Error::Other(format!("{e}: {text}"))),
+    };
+    match envelope.result {
+        Some(result) if envelope.success && status.is_success() => Ok(result),
+        _ => {
+            let errors: Vec<String> = envelope
+                .errors
+                .iter()
+                .map(|e| format!("{} {}", e.code, e.message))
+                .collect();
+            Err(Error::Status(status.as_u16(), errors.join(", ")))
+        }
+    }
+}
+
+// `a.b.example.com`, `b.example.com`, `example.com`
+fn candidate_zones(hostname: &str) -> Vec<&str> {
+    let hostname = hostname.trim_end_matches('.');
+    let mut zones = vec![hostname];
+    let mut rest = hostname;
+    while let Some((_, parent)) = rest.split_once('.') {
+        if !parent.contains('.') {
+            break;
+        }
+        zones.push(parent);
+        rest = parent;
+    }
+    zones
+}
+
+pub struct Cloudflare {
+    zone: Option<String>,
+    // record ids by type, looked up once and reused between cycles
+    records: HashMap<&'static str, String>,
+}
+
+impl Cloudflare {
+    pub fn new() -> Self {
+        Cloudflare {
+            zone: None,
+            records: HashMap::new(),
+        }
+    }
+
+    fn zone(&mut self) -> Result<String, Error> {
+        if let Some(zone) = &self.zone {
+            return Ok(zone.clone());
+        }
+        let candidates = match &CONFIG.cloudflare.zone {
+            Some(zone) => vec![zone.as_str()],
+            None => candidate_zones(&CONFIG.hostname),
+        };
+        for name in candidates {
+            let zones: Vec<Zone> = call(
+                CLIENT
+                    .get(&format!("{CLOUDFLARE_URL}/zones"))
+                    .query(&ZoneQuery { name }),
+                None,
+            )?;
+            if let Some(zone) = zones.into_iter().next() {
+                debug!("zone {} has id {}", zone.name, zone.id);
+                self.zone = Some(zone.id.clone());
+                return Ok(zone.id);
+            }
+        }
+        Err(Error::Status(
+            404,
+            format!("no cloudflare zone found for {}", CONFIG.hostname),
+        ))
+    }
+
+    fn find(&self, zone: &str, kind: &str) -> Result<Option<DnsRecord>, Error> {
+        let records: Vec<DnsRecord> = call(
+            CLIENT
+                .get(&format!("{CLOUDFLARE_URL}/zones/{zone}/dns_records"))
+                .query(&RecordQuery {
+                    kind,
+                    name: &CONFIG.hostname,
+                }),
+            None,
+        )?;
+        Ok(records.into_iter().next())
+    }
+
+    fn set(&mut self, zone: &str, kind: &'static str, ip: IpAddr) -> Result<(), Error> {
+        let options = &CONFIG.options;
+        let content = ip.to_string();
+        let record = NewRecord {
+            kind,
+            name: &CONFIG.hostname,
+            content: &content,
+            ttl: options.ttl.unwrap_or(CONFIG.cloudflare.ttl),
+            proxied: options.proxied.unwrap_or(CONFIG.cloudflare.proxied),
+            comment: options.comment.as_deref(),
+        };
+        let url = format!("{CLOUDFLARE_URL}/zones/{zone}/dns_records");
+
+        if let Some(id) = self.records.get(kind) {
+            let res: Result<DnsRecord, Error> =
+                call(CLIENT.patch(&format!("{url}/{id}")), Some(&record));
+            match res {
+                // deleted behind our back, look it up again
+                Err(Error::Status(404, _)) => {
+                    self.records.remove(kind);
+                }
+                res => {
+                    res?;
+                    info!("updated {kind} {}: {content}", CONFIG.hostname);
+                    return Ok(());
+                }
+            }
+        }
+
+        let existing = self.find(zone, kind)?;
+        let updated: DnsRecord = match existing {
+            Some(r)
+                if r.content.parse::<IpAddr>().ok() == Some(ip)
+                    && r.ttl == record.ttl
+                    && r.proxied == record.proxied
+                    && record
+                        .comment
+                        .is_none_or(|c| r.comment.as_deref() == Some(c)) =>
+            {
+                debug!("{kind} {} is already {content}", CONFIG.hostname);
+                r
+            }
+            Some(r) => {
+                let r = call(CLIENT.patch(&format!("{url}/{}", r.id)), Some(&record))?;
+                info!("updated {kind} {}: {content}", CONFIG.hostname);
+                r
+            }
+            None => {
+                let r = call(CLIENT.post(&url), Some(&record))?;
+                info!("created {kind} {}: {content}", CONFIG.hostname);
+                r
+            }
+        };
+        self.records.insert(kind, updated.id);
+        Ok(())
+    }
+
+    fn apply(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Result<(), Error> {
+        let zone = self.zone()?;
+        if let Some(v4) = v4 {
+            self.set(&zone, "A", IpAddr::V4(v4))?;
+        }
+        if let Some(v6) = v6 {
+            self.set(&zone, "AAAA", IpAddr::V6(v6))?;
+        }
+        Ok(())
+    }
+}
+
+impl Provider for Cloudflare {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        match self.apply(v4, v6) {
+            Ok(()) => Outcome::Success,
+            Err(err) if err.is_transient() => Outcome::Transient(err.to_string()),
+            Err(err) => Outcome::Permanent(err.to_string()),
+        }
+    }
+
+    fn supports(&self, _option: &str) -> bool {
+        true
+    }
+
+    fn resolvable(&self) -> bool {
+        !CONFIG.options.proxied.unwrap_or(CONFIG.cloudflare.proxied)
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use crate::config::CONFIG;
+
+pub struct Debounce<T> {
+    candidate: Option<T>,
+    count: u32,
+    since: Instant,
+    startup: bool,
+    checks: u32,
+    seconds: f64,
+}
+
+impl<T: PartialEq + Clone> Debounce<T> {
+    pub fn new() -> Self {
+        Debounce {
+            candidate: None,
+            count: 0,
+            since: Instant::now(),
+            startup: true,
+            checks: CONFIG.debounce.checks,
+            seconds: CONFIG.debounce.seconds,
+        }
+    }
+
+    // the first address seen after startup is published right away,
+    // later changes must be observed for `checks` polls or `seconds`
+    pub fn stable(&mut self, ip: &T) -> bool {
+        if std::mem::take(&mut self.startup) {
+            return true;
+        }
+        let (checks, seconds) = (self.checks, self.seconds);
+        if checks == 0 && seconds <= 0.0 {
+            return true;
+        }
+        if self.candidate.as_ref() != Some(ip) {
+            self.candidate = Some(ip.clone());
+            self.count = 0;
+            self.since = Instant::now();
+        }
+        self.count += 1;
+        (checks > 0 && self.count >= checks)
+            || (seconds > 0.0 && self.since.elapsed() >= Duration::from_secs_f64(seconds))
+    }
+
+    pub fn reset(&mut self) {
+        self.startup = false;
+        self.candidate = None;
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(checks: u32, seconds: f64) -> Debounce<u8> {
+        Debounce {
+            candidate: None,
+            count: 0,
+            since: Instant::now(),
+            startup: true,
+            checks,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn startup() {
+        let mut debounce = with(3, 0.0);
+        assert!(debounce.stable(&1));
+        assert!(!debounce.stable(&2));
+
+        let mut debounce = with(3, 0.0);
+        debounce.reset();
+        assert!(!debounce.stable(&1));
+    }
+
+    #[test]
+    fn disabled() {
+        let mut debounce = with(0, 0.0);
+        debounce.reset();
+        assert!(debounce.stable(&1));
+        assert!(debounce.stable(&2));
+    }
+
+    #[test]
+    fn checks() {
+        let mut debounce = with(3, 0.0);
+        debounce.reset();
+        assert!(!debounce.stable(&1));
+        assert!(!debounce.stable(&1));
+        assert!(debounce.stable(&1));
+    }
+
+    #[test]
+    fn candidate_change() {
+        let mut debounce = with(3, 0.0);
+        debounce.reset();
+        assert!(!debounce.stable(&1));
+        assert!(!debounce.stable(&1));
+        assert!(!debounce.stable(&2));
+        assert!(!debounce.stable(&2));
+        assert!(debounce.stable(&2));
+    }
+
+    #[test]
+    fn seconds() {
+        let mut debounce = with(0, 0.05);
+        debounce.reset();
+        assert!(!debounce.stable(&1));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!debounce.stable(&2));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(debounce.stable(&2));
+    }
+}
@@ -0,0 +1,168 @@
+use serde::Serialize;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::api::dyndns;
+use crate::api::{Outcome, Provider};
+use crate::config::CONFIG;
+use crate::{Error, CLIENT};
+
+const DYNDNS_URL: &str = "https://update.dedyn.io/nic/update";
+const REST_URL: &str = "https://desec.io/api/v1";
+
+// an unchanged family keeps its record instead of being removed
+const PRESERVE: &str = "preserve";
+
+#[derive(Serialize)]
+struct Params {
+    hostname: &'static str,
+    myipv4: String,
+    myipv6: String,
+}
+
+#[derive(Serialize)]
+struct RRset<'a> {
+    subname: &'a str,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    ttl: u32,
+    records: Vec<String>,
+}
+
+pub struct DeSec {
+    token: &'static str,
+    domain: &'static str,
+    // `hostname` relative to `domain`, empty for the apex
+    subname: String,
+    // the dyndns endpoint, or the REST api root with `desec.rest`
+    url: String,
+}
+
+impl DeSec {
+    pub fn new() -> Self {
+        let config = &CONFIG.desec;
+        let host = CONFIG.hostname.trim_end_matches('.');
+        let domain = config.domain.as_deref().unwrap_or(host);
+        let subname = match host.strip_suffix(domain) {
+            Some(sub) if sub.is_empty() || sub.ends_with('.') => sub.trim_end_matches('.'),
+            _ => panic!("hostname {host} is not part of desec.domain {domain} !"),
+        };
+        DeSec {
+            token: config.token.as_deref().unwrap_or(&CONFIG.token),
+            domain,
+            subname: subname.to_string(),
+            url: if config.rest { REST_URL } else { DYNDNS_URL }.to_string(),
+        }
+    }
+
+    fn dyndns(&self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        let params = Params {
+            hostname: &CONFIG.hostname,
+            myipv4: v4.map_or(PRESERVE.to_string(), |ip| ip.to_string()),
+            myipv6: v6.map_or(PRESERVE.to_string(), |ip| ip.to_string()),
+        };
+        let res = CLIENT
+            .get(&self.url)
+            .basic_auth(&CONFIG.hostname, self.token)
+            .query(&params)
+            .send();
+        dyndns::outcome(res)
+    }
+
+    // one bulk PATCH, deSEC applies all RRsets or none
+    fn rest(&self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Result<(), Error> {
+        let ttl = CONFIG.options.ttl.unwrap_or(CONFIG.desec.ttl);
+        let mut rrsets = Vec::new();
+        if let Some(v4) = v4 {
+            rrsets.push(RRset {
+                subname: &self.subname,
+                kind: "A",
+                ttl,
+                records: vec![v4.to_string()],
+            });
+        }
+        if let Some(v6) = v6 {
+            rrsets.push(RRset {
+                subname: &self.subname,
+                kind: "AAAA",
+                ttl,
+                records: vec![v6.to_string()],
+            });
+        }
+        let res = CLIENT
+            .patch(&format!("{}/domains/{}/rrsets/", self.url, self.domain))
+            .header("Authorization", &format!("Token {}", self.token))
+            .send_json(&rrsets)?;
+        let status = res.status();
+        if !status.is_success() {
+            let text = res.text().unwrap_or_default();
+            return Err(Error::Status(status.as_u16(), text.trim().to_string()));
+        }
+        info!("updated {}", CONFIG.hostname);
+        Ok(())
+    }
+}
+
+impl Provider for DeSec {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        if !CONFIG.desec.rest {
+            return self.dyndns(v4, v6);
+        }
+        match self.rest(v4, v6) {
+            Ok(()) => Outcome::Success,
+            Err(err) if err.is_transient() => Outcome::Transient(err.to_string()),
+            Err(err) => Outcome::Permanent(err.to_string()),
+        }
+    }
+
+    // the dyndns endpoint only takes addresses
+    fn supports(&self, option: &str) -> bool {
+        CONFIG.desec.rest && option == "ttl"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+
+    fn desec(url: &str) -> DeSec {
+        DeSec {
+            token: "secret",
+            domain: "example.dedyn.io",
+            subname: "home".to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn dyndns() {
+        let (url, rx) = serve(200, "good");
+        let v6 = "2001:db8::1".parse().unwrap();
+        let outcome = desec(&url).dyndns(None, Some(v6));
+        assert!(matches!(outcome, Outcome::Success));
+        let request = rx.recv().unwrap();
+        assert!(request.contains("myipv4=preserve&myipv6=2001%3Adb8%3A%3A1 "));
+        assert!(request.contains("Authorization: Basic "));
+
+        let (url, _) = serve(401, "badauth");
+        let outcome = desec(&url).dyndns(Some("192.0.2.1".parse().unwrap()), None);
+        assert!(matches!(outcome, Outcome::Permanent(_)));
+    }
+
+    #[test]
+    fn rest() {
+        let (url, rx) = serve(200, "[]");
+        let v4 = "192.0.2.1".parse().unwrap();
+        desec(&url).rest(Some(v4), None).unwrap();
+        let request = rx.recv().unwrap();
+        assert!(request.starts_with("PATCH /domains/example.dedyn.io/rrsets/ "));
+        assert!(request.contains("Authorization: Token secret"));
+        assert!(request
+            .ends_with(r#"[{"subname":"home","type":"A","ttl":3600,"records":["192.0.2.1"]}]"#));
+
+        let (url, _) = serve(429, "throttled");
+        assert!(desec(&url).rest(Some(v4), None).unwrap_err().is_transient());
+        let (url, _) = serve(400, "bad request");
+        assert!(!desec(&url).rest(Some(v4), None).unwrap_err().is_transient());
+    }
+}
@@ -0,0 +1,195 @@
+use serde::Serialize;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::api::{Outcome, Provider};
+use crate::config::{CONFIG, IPV4_FILE};
+use crate::requests::Response;
+use crate::state;
+use crate::{Error, CLIENT};
+
+const DUCKDNS_URL: &str = "https://www.duckdns.org/update";
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Ok,
+    // bad token or a domain not owned by it, nothing more specific
+    Ko,
+    Unknown(String),
+}
+
+impl From<&str> for Reply {
+    fn from(text: &str) -> Self {
+        match text.trim() {
+            "OK" => Reply::Ok,
+            "KO" => Reply::Ko,
+            text => Reply::Unknown(text.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Params {
+    domains: String,
+    token: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<Ipv4Addr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ipv6: Option<Ipv6Addr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear: Option<bool>,
+}
+
+// `home.duckdns.org` and `home` are the same domain
+fn domain(name: &str) -> &str {
+    let name = name.trim().trim_end_matches('.');
+    name.strip_suffix(".duckdns.org").unwrap_or(name)
+}
+
+pub struct DuckDns {
+    domains: String,
+    token: &'static str,
+    url: String,
+    // resent with an IPv6 only update, without `ip` DuckDNS would take
+    // the address the request came from
+    v4: Option<Ipv4Addr>,
+    // whether the stale records were cleared, done once
+    cleared: bool,
+}
+
+impl DuckDns {
+    pub fn new() -> Self {
+        let config = &CONFIG.duckdns;
+        let domains: Vec<&str> = if config.domains.is_empty() {
+            vec![domain(&CONFIG.hostname)]
+        } else {
+            config.domains.iter().map(|d| domain(d)).collect()
+        };
+        if config.clear && !CONFIG.no_ipv6 {
+            warn!("duckdns.clear is ignored unless no_ipv6 is set");
+        }
+        DuckDns {
+            domains: domains.join(","),
+            token: config.token.as_deref().unwrap_or(&CONFIG.token),
+            url: DUCKDNS_URL.to_string(),
+            v4: if CONFIG.no_ipv4 {
+                None
+            } else {
+                CONFIG.current_ip.v4.or_else(|| state::load(IPV4_FILE))
+            },
+            cleared: false,
+        }
+    }
+
+    fn send(&self, params: &Params) -> Result<Response, Error> {
+        CLIENT.get(&self.url).query(params).send()
+    }
+
+    fn params(&self) -> Params {
+        Params {
+            domains: self.domains.clone(),
+            token: self.token,
+            ip: None,
+            ipv6: None,
+            clear: None,
+        }
+    }
+}
+
+fn outcome(res: Result<Response, Error>) -> Outcome {
+    match res {
+        Ok(res) => {
+            let status = res.status();
+            let text = match res.text() {
+                Ok(text) => text.trim().to_string(),
+                Err(err) => format!("{err:?}"),
+            };
+            match Reply::from(text.as_str()) {
+                Reply::Ok if status.is_success() => Outcome::Success,
+                Reply::Ko => Outcome::Permanent("KO, check the token and domains".to_string()),
+                _ if status.is_success() || status.is_transient() => {
+                    Outcome::Transient(format!("code: {status}, msg: {text}"))
+                }
+                _ => Outcome::Permanent(format!("code: {status}, msg: {text}")),
+            }
+        }
+        Err(err) => Outcome::Transient(err.to_string()),
+    }
+}
+
+impl Provider for DuckDns {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        if CONFIG.duckdns.clear && CONFIG.no_ipv6 && !self.cleared {
+            let mut params = self.params();
+            params.clear = Some(true);
+            match outcome(self.send(&params)) {
+                Outcome::Success => debug!("cleared {}", self.domains),
+                outcome => return outcome,
+            }
+            self.cleared = true;
+        }
+        let mut params = self.params();
+        params.ip = v4.or(self.v4);
+        params.ipv6 = v6;
+        let outcome = outcome(self.send(&params));
+        if let Outcome::Success = outcome {
+            info!("updated {}", self.domains);
+            self.v4 = params.ip;
+        }
+        outcome
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.domains
+            .split(',')
+            .map(|domain| format!("{domain}.duckdns.org"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+
+    fn publish(status: u16, body: &str) -> Outcome {
+        let (url, _) = serve(status, body);
+        outcome(CLIENT.get(&url).send())
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(Reply::from("OK"), Reply::Ok);
+        assert_eq!(Reply::from("OK\n"), Reply::Ok);
+        assert_eq!(Reply::from("KO"), Reply::Ko);
+        assert_eq!(Reply::from("ok"), Reply::Unknown("ok".to_string()));
+        assert_eq!(Reply::from(""), Reply::Unknown(String::new()));
+    }
+
+    #[test]
+    fn outcomes() {
+        assert!(matches!(publish(200, "OK"), Outcome::Success));
+        assert!(matches!(publish(200, "KO"), Outcome::Permanent(_)));
+        assert!(matches!(publish(200, "what"), Outcome::Transient(_)));
+        assert!(matches!(publish(502, "OK"), Outcome::Transient(_)));
+        assert!(matches!(publish(404, "not found"), Outcome::Permanent(_)));
+        let refused = CLIENT.get("http://127.0.0.1:1/").send();
+        assert!(matches!(outcome(refused), Outcome::Transient(_)));
+    }
+
+    #[test]
+    fn keeps_ipv4() {
+        let (url, rx) = serve(200, "OK");
+        let mut duckdns = DuckDns {
+            domains: "home".to_string(),
+            token: "secret",
+            url,
+            v4: Some("192.0.2.1".parse().unwrap()),
+            cleared: false,
+        };
+        let v6 = "2001:db8::1".parse().unwrap();
+        assert!(matches!(duckdns.publish(None, Some(v6)), Outcome::Success));
+        let request = rx.recv().unwrap();
+        assert!(request
+            .starts_with("GET /?domains=home&token=secret&ip=192.0.2.1&ipv6=2001%3Adb8%3A%3A1 "));
+    }
+}
@@ -1,28 +1,99 @@
-use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use serde::{Serialize, Serializer};
 
-use crate::api::API;
-use crate::config::{CONFIG, IPV4_FILE, IPV6_FILE};
-use crate::util;
-use crate::CLIENT;
+use crate::api::{Outcome, Provider};
+use crate::config::CONFIG;
+use crate::requests::Response;
+use crate::{Error, CLIENT};
 
-const DYNV6_URL: &'static str = "https://dynv6.com/nic/update";
-const DYNDNS_GOOD: &'static str = "good";
+const UPDATE_PATH: &str = "/nic/update";
+
+// `https://dyn.example.com` and `https://dyn.example.com/nic/update` both work
+fn update_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let path = url.split_once("://").map_or(url, |(_, rest)| rest);
+    if path.contains('/') {
+        url.to_string()
+    } else {
+        format!("{url}{UPDATE_PATH}")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Code {
+    Good,
+    NoChg,
+    BadAuth,
+    NotFqdn,
+    NoHost,
+    NumHost,
+    Abuse,
+    BadAgent,
+    DnsErr,
+    ServerError,
+    Unknown(String),
+}
+
+impl From<&str> for Code {
+    // "good 1.2.3.4", one line per host when several are updated
+    fn from(text: &str) -> Self {
+        let code = text.split_whitespace().next().unwrap_or_default();
+        match code {
+            "good" => Code::Good,
+            "nochg" => Code::NoChg,
+            "badauth" => Code::BadAuth,
+            "notfqdn" => Code::NotFqdn,
+            "nohost" => Code::NoHost,
+            "numhost" => Code::NumHost,
+            "abuse" => Code::Abuse,
+            "badagent" => Code::BadAgent,
+            "dnserr" => Code::DnsErr,
+            "911" => Code::ServerError,
+            _ => Code::Unknown(text.to_string()),
+        }
+    }
+}
+
+impl Code {
+    fn is_success(&self) -> bool {
+        matches!(self, Code::Good | Code::NoChg)
+    }
+
+    // the protocol forbids retrying these without user intervention
+    fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Code::BadAuth
+                | Code::NotFqdn
+                | Code::NoHost
+                | Code::NumHost
+                | Code::Abuse
+                | Code::BadAgent
+        )
+    }
+
+    fn is_server_error(&self) -> bool {
+        matches!(self, Code::DnsErr | Code::ServerError)
+    }
+}
 
 #[derive(Serialize)]
 struct Params {
     hostname: &'static str,
-    #[serde(serialize_with = "as_myip")]
-    myip: Vec<String>,
+    #[serde(serialize_with = "as_myip", skip_serializing_if = "Vec::is_empty")]
+    myip: Vec<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    myipv6: Option<Ipv6Addr>,
 }
 
 fn as_myip<T, S>(myip: &T, serializer: S) -> Result<S::Ok, S::Error>
 where
-    T: AsRef<Vec<String>>,
+    T: AsRef<Vec<IpAddr>>,
     S: Serializer,
 {
-    serializer.serialize_str(myip.as_ref().join(",").as_str())
+    let myip: Vec<String> = myip.as_ref().iter().map(|ip| ip.to_string()).collect();
+    serializer.serialize_str(myip.join(",").as_str())
 }
 
 impl Params {
@@ -30,89 +101,161 @@ impl Params {
         Params {
             hostname: &CONFIG.hostname,
             myip: Vec::new(),
+            myipv6: None,
         }
     }
 }
 
 pub struct DynDNS {
-    v4: String,
-    v6: String,
-    new_v4: Option<String>,
-    new_v6: Option<String>,
     params: Params,
+    url: String,
     username: &'static str,
     password: &'static str,
 }
 
-impl API for DynDNS {
-    fn new() -> Self {
+impl DynDNS {
+    pub fn new() -> Self {
+        let config = &CONFIG.dyndns;
         DynDNS {
-            v4: CONFIG.current_ip.v4.clone(),
-            v6: CONFIG.current_ip.v6.clone(),
-            new_v4: None,
-            new_v6: None,
             params: Params::new(),
-            username: "none",
-            password: &CONFIG.token,
+            url: update_url(&config.url),
+            username: &config.username,
+            password: config.password.as_deref().unwrap_or(&CONFIG.token),
         }
     }
-    fn check_v4(&mut self) {
-        debug!("check v4");
-        if let Some(new_v4) = util::ipv4() {
-            let new_v4 = new_v4.to_string();
-            if new_v4 != self.v4 {
-                info!("old ipv4: {}, current ipv4: {}", self.v4, new_v4);
-                self.params.myip.push(new_v4.clone());
-                self.new_v4 = Some(new_v4);
+}
+
+impl Provider for DynDNS {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        self.params.myip = Vec::new();
+        self.params.myipv6 = None;
+        if let Some(v4) = v4 {
+            self.params.myip.push(IpAddr::V4(v4));
+        }
+        if let Some(v6) = v6 {
+            if CONFIG.dyndns.myipv6 {
+                self.params.myipv6 = Some(v6);
+            } else {
+                self.params.myip.push(IpAddr::V6(v6));
             }
         }
+        let res = CLIENT
+            .get(&self.url)
+            .basic_auth(self.username, self.password)
+            .query(&self.params)
+            .send();
+        outcome(res)
     }
-    fn check_v6(&mut self) {
-        debug!("check v6");
-        if let Some(new_v6) = util::ipv6() {
-            let new_v6 = new_v6.to_string();
-            if new_v6 != self.v6 {
-                info!("old ipv6: {}, current ipv6: {}", self.v6, new_v6);
-                self.params.myip.push(new_v6.clone());
-                self.new_v6 = Some(new_v6)
+}
+
+/// Interprets a DynDNS2 reply, shared with the services speaking the
+/// same protocol under their own parameters.
+pub fn outcome(res: Result<Response, Error>) -> Outcome {
+    match res {
+        Ok(res) => {
+            let status = res.status();
+            let text = match res.text() {
+                Ok(text) => text.trim().to_string(),
+                Err(err) => format!("{err:?}"),
+            };
+            let code = Code::from(text.as_str());
+            if code.is_success() {
+                info!("{text}");
+                Outcome::Success
+            } else if code.is_fatal() {
+                Outcome::Permanent(text)
+            } else if code.is_server_error() || status.is_success() || status.is_transient() {
+                Outcome::Transient(format!("code: {status}, msg: {text}"))
+            } else {
+                Outcome::Permanent(format!("code: {status}, msg: {text}"))
             }
         }
+        Err(err) => Outcome::Transient(err.to_string()),
     }
-    fn update(&mut self) {
-        if self.params.myip.is_empty() {
-            return;
-        }
-        info!("ipv4/ipv6 address changed, start update");
-        match CLIENT
-            .get(DYNV6_URL)
-            .basic_auth(&self.username, &self.password)
-            .query(&self.params)
-            .send()
-        {
-            Ok(res) => {
-                let status = res.status();
-                let text = match res.text() {
-                    Ok(text) => text.trim().to_string(),
-                    Err(err) => format!("{err:?}"),
-                };
-                if status.is_success() && text == DYNDNS_GOOD {
-                    info!("{DYNDNS_GOOD}");
-                    if let Some(v4) = &self.new_v4 {
-                        fs::write(IPV4_FILE, &v4).ok();
-                        self.v4 = v4.to_string();
-                    }
-                    if let Some(v6) = &self.new_v6 {
-                        fs::write(IPV6_FILE, &v6).ok();
-                        self.v6 = v6.to_string();
-                    }
-                } else {
-                    error!("code: {status}, msg: {text}");
-                }
-            }
-            Err(err) => error!("{err}"),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+
+    fn publish(status: u16, body: &str) -> Outcome {
+        let (url, _) = serve(status, body);
+        outcome(CLIENT.get(&url).send())
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(Code::from("good 1.2.3.4"), Code::Good);
+        assert_eq!(Code::from("nochg 1.2.3.4\nnochg ::1"), Code::NoChg);
+        assert_eq!(Code::from("badauth"), Code::BadAuth);
+        assert_eq!(Code::from("notfqdn"), Code::NotFqdn);
+        assert_eq!(Code::from("nohost"), Code::NoHost);
+        assert_eq!(Code::from("numhost"), Code::NumHost);
+        assert_eq!(Code::from("abuse"), Code::Abuse);
+        assert_eq!(Code::from("badagent"), Code::BadAgent);
+        assert_eq!(Code::from("dnserr"), Code::DnsErr);
+        assert_eq!(Code::from("911"), Code::ServerError);
+        assert_eq!(Code::from("goodbye"), Code::Unknown("goodbye".to_string()));
+        assert_eq!(Code::from(""), Code::Unknown(String::new()));
+    }
+
+    #[test]
+    fn update_urls() {
+        assert_eq!(update_url("https://a.b"), "https://a.b/nic/update");
+        assert_eq!(update_url("https://a.b/"), "https://a.b/nic/update");
+        assert_eq!(update_url("https://a.b/x/update"), "https://a.b/x/update");
+    }
+
+    #[test]
+    fn success() {
+        assert!(matches!(publish(200, "good 1.2.3.4"), Outcome::Success));
+        assert!(matches!(publish(200, "nochg 1.2.3.4"), Outcome::Success));
+    }
+
+    // the host locks updates on a permanent outcome
+    #[test]
+    fn lockout() {
+        for body in [
+            "badauth", "notfqdn", "nohost", "numhost", "abuse", "badagent",
+        ] {
+            assert!(
+                matches!(publish(200, body), Outcome::Permanent(_)),
+                "{body}"
+            );
         }
-        self.params.myip = Vec::new();
-        self.new_v4 = None;
-        self.new_v6 = None;
+        assert!(matches!(publish(401, "badauth"), Outcome::Permanent(_)));
+        assert!(matches!(publish(404, "not found"), Outcome::Permanent(_)));
+    }
+
+    // and backs off on a transient one
+    #[test]
+    fn backoff() {
+        assert!(matches!(publish(200, "dnserr"), Outcome::Transient(_)));
+        assert!(matches!(publish(200, "911"), Outcome::Transient(_)));
+        assert!(matches!(publish(200, "what"), Outcome::Transient(_)));
+        assert!(matches!(publish(502, "bad gateway"), Outcome::Transient(_)));
+        assert!(matches!(publish(429, "slow down"), Outcome::Transient(_)));
+        let refused = CLIENT.get("http://127.0.0.1:1/").send();
+        assert!(matches!(outcome(refused), Outcome::Transient(_)));
+    }
+
+    #[test]
+    fn request() {
+        let (url, request) = serve(200, "good");
+        let mut dyndns = DynDNS {
+            params: Params::new(),
+            url: update_url(&url),
+            username: "user",
+            password: "secret",
+        };
+        let v4 = "192.0.2.1".parse().ok();
+        let v6 = "2001:db8::1".parse().ok();
+        assert!(matches!(dyndns.publish(v4, v6), Outcome::Success));
+        let request = request.recv().unwrap();
+        assert!(request.starts_with("GET /nic/update?hostname="));
+        assert!(request.contains("&myip=192.0.2.1%2C2001%3Adb8%3A%3A1 HTTP/1.1"));
+        // user:secret
+        assert!(request.contains("Authorization: Basic dXNlcjpzZWNyZXQ="));
     }
 }
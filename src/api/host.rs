@@ -0,0 +1,324 @@
+use signal_hook::{consts::TERM_SIGNALS, flag};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::api::backoff::Backoff;
+use crate::api::debounce::Debounce;
+use crate::api::lockout::Lockout;
+use crate::api::reconcile::Reconcile;
+use crate::api::verify::{Status, Verify};
+use crate::api::{Outcome, Provider};
+use crate::config::{Source, CONFIG, IPV4_FILE, IPV6_FILE, STATUS_FILE, UPDATED_FILE};
+use crate::state;
+use crate::util::{self, or_unknown};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// address detection, state and retries, the same for every provider
+pub struct Host {
+    provider: Box<dyn Provider>,
+    v4: Option<Ipv4Addr>,
+    v6: Option<Ipv6Addr>,
+    // the address last detected, whether published or not
+    detected_v4: Option<Ipv4Addr>,
+    detected_v6: Option<Ipv6Addr>,
+    // the pending change, kept until it is published or given up
+    new_v4: Option<Ipv4Addr>,
+    new_v6: Option<Ipv6Addr>,
+    debounce_v4: Debounce<Ipv4Addr>,
+    debounce_v6: Debounce<Ipv6Addr>,
+    // given up after too many failures, skipped until the address
+    // changes, an update succeeds or `retry.cap` has passed
+    abandoned_v4: Option<Ipv4Addr>,
+    abandoned_v6: Option<Ipv6Addr>,
+    abandoned_at: Option<Instant>,
+    backoff: Backoff,
+    lockout: Lockout,
+    // off when the names don't resolve to the published addresses
+    verifies: bool,
+    verify: Option<Verify>,
+    reconcile: Option<Reconcile>,
+    // unix time of the last successful update
+    updated: Option<u64>,
+}
+
+impl Host {
+    pub fn new(provider: Box<dyn Provider>) -> Self {
+        let reconcile = match CONFIG.reconcile.source {
+            _ if !CONFIG.reconcile.enabled => None,
+            Source::Dns if !provider.resolvable() => {
+                warn!("proxied records can't be reconciled from dns, reconcile disabled");
+                None
+            }
+            _ => Some(Reconcile::new()),
+        };
+        let verifies = CONFIG.verify.enabled && provider.resolvable();
+        if CONFIG.verify.enabled && !verifies {
+            warn!("proxied records can't be verified from dns, verify disabled");
+        }
+        Host {
+            provider,
+            v4: CONFIG.current_ip.v4.or_else(|| state::load(IPV4_FILE)),
+            v6: CONFIG.current_ip.v6.or_else(|| state::load(IPV6_FILE)),
+            detected_v4: None,
+            detected_v6: None,
+            new_v4: None,
+            new_v6: None,
+            debounce_v4: Debounce::new(),
+            debounce_v6: Debounce::new(),
+            abandoned_v4: None,
+            abandoned_v6: None,
+            abandoned_at: None,
+            backoff: Backoff::new(),
+            lockout: Lockout::load(),
+            verifies,
+            verify: None,
+            reconcile,
+            updated: state::load(UPDATED_FILE),
+        }
+    }
+
+    fn check_v4(&mut self) {
+        debug!("check v4");
+        if let Some(new_v4) = util::ipv4() {
+            self.detected_v4 = Some(new_v4);
+            if self.abandoned_v4.is_some_and(|ip| ip != new_v4) {
+                self.abandoned_v4 = None;
+                if self.abandoned_v6.is_none() {
+                    self.abandoned_at = None;
+                }
+            }
+            if Some(new_v4) == self.v4 {
+                self.debounce_v4.reset();
+                // a resend of the unchanged address stays pending
+                if self.new_v4 != Some(new_v4) {
+                    self.new_v4 = None;
+                }
+            } else if Some(new_v4) == self.new_v4 {
+                debug!("ipv4 {new_v4} already pending");
+            } else if Some(new_v4) == self.abandoned_v4 {
+                debug!("ipv4 {new_v4} was given up");
+            } else if self.debounce_v4.stable(&new_v4) {
+                info!(
+                    "old ipv4: {}, current ipv4: {}",
+                    or_unknown(&self.v4),
+                    new_v4
+                );
+                self.new_v4 = Some(new_v4);
+            } else {
+                debug!("ipv4 {new_v4} not stable yet");
+            }
+        }
+    }
+
+    fn check_v6(&mut self) {
+        debug!("check v6");
+        if let Some(new_v6) = util::ipv6() {
+            self.detected_v6 = Some(new_v6);
+            if self.abandoned_v6.is_some_and(|ip| ip != new_v6) {
+                self.abandoned_v6 = None;
+                if self.abandoned_v4.is_none() {
+                    self.abandoned_at = None;
+                }
+            }
+            if Some(new_v6) == self.v6 {
+                self.debounce_v6.reset();
+                // a resend of the unchanged address stays pending
+                if self.new_v6 != Some(new_v6) {
+                    self.new_v6 = None;
+                }
+            } else if Some(new_v6) == self.new_v6 {
+                debug!("ipv6 {new_v6} already pending");
+            } else if Some(new_v6) == self.abandoned_v6 {
+                debug!("ipv6 {new_v6} was given up");
+            } else if self.debounce_v6.stable(&new_v6) {
+                info!(
+                    "old ipv6: {}, current ipv6: {}",
+                    or_unknown(&self.v6),
+                    new_v6
+                );
+                self.new_v6 = Some(new_v6);
+            } else {
+                debug!("ipv6 {new_v6} not stable yet");
+            }
+        }
+    }
+
+    // the endpoint may have recovered in the meantime
+    fn forget_abandoned(&mut self) {
+        let cap = Duration::from_secs_f64(CONFIG.retry.cap);
+        if self.abandoned_at.is_some_and(|at| at.elapsed() >= cap) {
+            self.abandoned_v4 = None;
+            self.abandoned_v6 = None;
+            self.abandoned_at = None;
+        }
+    }
+
+    fn update(&mut self) {
+        if self.new_v4.is_none() && self.new_v6.is_none() {
+            return;
+        }
+        if self.lockout.is_locked() {
+            debug!("updates are locked");
+            return;
+        }
+        if !self.backoff.ready() {
+            debug!("backing off");
+            return;
+        }
+        info!("ipv4/ipv6 address changed, start update");
+        match self.provider.publish(self.new_v4, self.new_v6) {
+            Outcome::Success => {
+                self.backoff.reset();
+                self.abandoned_v4 = None;
+                self.abandoned_v6 = None;
+                self.abandoned_at = None;
+                self.updated = Some(now());
+                state::save(UPDATED_FILE, &now());
+                if self.verifies {
+                    state::save(STATUS_FILE, &"accepted, verifying");
+                    let names = self.provider.names();
+                    self.verify = Some(Verify::new(names, self.new_v4, self.new_v6));
+                }
+                if let Some(v4) = self.new_v4 {
+                    state::save(IPV4_FILE, &v4);
+                    self.v4 = Some(v4);
+                }
+                if let Some(v6) = self.new_v6 {
+                    state::save(IPV6_FILE, &v6);
+                    self.v6 = Some(v6);
+                }
+            }
+            Outcome::Transient(err) => {
+                if self.backoff.retry(&err) {
+                    return;
+                }
+                self.abandoned_v4 = self.new_v4;
+                self.abandoned_v6 = self.new_v6;
+                self.abandoned_at = Some(Instant::now());
+            }
+            Outcome::Permanent(err) => {
+                // stays pending, nothing is sent while locked
+                self.lockout.lock(&err);
+                return;
+            }
+        }
+        self.new_v4 = None;
+        self.new_v6 = None;
+    }
+
+    fn verify(&mut self) {
+        let Some(verify) = self.verify.as_mut() else {
+            return;
+        };
+        match verify.check() {
+            Status::Pending => return,
+            Status::Propagated => {
+                info!("update propagated to all nameservers");
+                state::save(STATUS_FILE, &"propagated");
+            }
+            Status::NotPropagated(missing) => {
+                warn!("update accepted but not propagated: {missing}");
+                state::save(
+                    STATUS_FILE,
+                    &format!("accepted but not propagated: {missing}"),
+                );
+            }
+        }
+        self.verify = None;
+    }
+
+    // republishes an unchanged address when the live record differs,
+    // e.g. after an edit in the web ui or with a stale state file
+    fn reconcile(&mut self) {
+        let Some(reconcile) = self.reconcile.as_mut() else {
+            return;
+        };
+        if self.new_v4.is_some()
+            || self.new_v6.is_some()
+            || self.verify.is_some()
+            || self.lockout.is_locked()
+            || !reconcile.due()
+        {
+            return;
+        }
+        let names = self.provider.names();
+        // a new address is left to the debounce, it is published anyway
+        let v4 = self
+            .detected_v4
+            .filter(|ip| Some(*ip) == self.v4 && Some(*ip) != self.abandoned_v4);
+        if let Some(v4) = v4.filter(|_| !CONFIG.no_ipv4) {
+            if reconcile.drifted(&names, IpAddr::V4(v4)) {
+                self.new_v4 = Some(v4);
+            }
+        }
+        let v6 = self
+            .detected_v6
+            .filter(|ip| Some(*ip) == self.v6 && Some(*ip) != self.abandoned_v6);
+        if let Some(v6) = v6.filter(|_| !CONFIG.no_ipv6) {
+            if reconcile.drifted(&names, IpAddr::V6(v6)) {
+                self.new_v6 = Some(v6);
+            }
+        }
+    }
+
+    // some providers expire hosts that weren't updated for a while
+    fn refresh(&mut self) {
+        let after = CONFIG.force_update_after;
+        if after <= 0.0
+            || self.new_v4.is_some()
+            || self.new_v6.is_some()
+            || self.lockout.is_locked()
+            // a refresh that was given up is retried after the cool-down
+            || self.abandoned_at.is_some()
+        {
+            return;
+        }
+        let age = self.updated.map(|updated| now().saturating_sub(updated));
+        if age.is_some_and(|age| (age as f64) < after) {
+            return;
+        }
+        let v4 = self.v4.filter(|_| !CONFIG.no_ipv4);
+        let v6 = self.v6.filter(|_| !CONFIG.no_ipv6);
+        if v4.is_none() && v6.is_none() {
+            return;
+        }
+        match age {
+            Some(age) => info!("last update {age}s ago, forcing a refresh"),
+            None => info!("last update unknown, forcing a refresh"),
+        }
+        self.new_v4 = v4;
+        self.new_v6 = v6;
+    }
+
+    pub fn run(&mut self) -> std::io::Result<()> {
+        let term_now = Arc::new(AtomicBool::new(false));
+        for sig in TERM_SIGNALS {
+            flag::register_conditional_shutdown(*sig, 1, Arc::clone(&term_now))?;
+            flag::register(*sig, Arc::clone(&term_now))?;
+        }
+
+        while !term_now.load(Ordering::Relaxed) {
+            self.forget_abandoned();
+            if !CONFIG.no_ipv4 {
+                self.check_v4();
+            }
+            if !CONFIG.no_ipv6 {
+                self.check_v6();
+            }
+            self.reconcile();
+            self.refresh();
+            self.update();
+            self.verify();
+            std::thread::sleep(Duration::from_secs_f64(CONFIG.interval));
+        }
+        info!("gracefully shutting down");
+        Ok(())
+    }
+}
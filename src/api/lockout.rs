@@ -0,0 +1,63 @@
+use std::fs;
+
+use crate::config::{API, CONFIG, LOCK_FILE};
+use crate::state;
+
+// FNV-1a, stable across builds unlike `DefaultHasher`
+fn fingerprint() -> String {
+    // only what the provider judged: the credentials, the record options
+    // and the section of the active api, unrelated settings don't unlock
+    let section = match CONFIG.api {
+        API::Update => format!("{:?}", CONFIG.update),
+        API::DynDNS => format!("{:?}", CONFIG.dyndns),
+        API::Record => format!("{:?}", CONFIG.record),
+        API::RFC2136 => format!("{:?}", CONFIG.rfc2136),
+        API::Cloudflare => format!("{:?}", CONFIG.cloudflare),
+        API::Webhook => format!("{:?}", CONFIG.webhook),
+        API::DuckDNS => format!("{:?}", CONFIG.duckdns),
+        API::DeSEC => format!("{:?}", CONFIG.desec),
+    };
+    let config = format!(
+        "{:?} {} {} {:?} {section}",
+        CONFIG.api, CONFIG.hostname, CONFIG.token, CONFIG.options
+    );
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in config.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+// a host rejected with a permanent error must not be retried until
+// the configuration changes, even across restarts
+pub struct Lockout {
+    reason: Option<String>,
+}
+
+impl Lockout {
+    pub fn load() -> Self {
+        let reason = state::load::<String>(LOCK_FILE).and_then(|content| {
+            let (fp, reason) = content.split_once(' ').unwrap_or((&content, ""));
+            if fp == fingerprint() {
+                error!("updates are locked after {reason:?}, fix the configuration to resume");
+                Some(reason.to_string())
+            } else {
+                info!("configuration changed, clearing lock after {reason:?}");
+                fs::remove_file(LOCK_FILE).ok();
+                None
+            }
+        });
+        Lockout { reason }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.reason.is_some()
+    }
+
+    pub fn lock(&mut self, reason: &str) {
+        error!("{reason}: updates are locked until the configuration changes");
+        state::save(LOCK_FILE, &format!("{} {reason}", fingerprint()));
+        self.reason = Some(reason.to_string());
+    }
+}
@@ -1,43 +1,77 @@
+mod backoff;
+pub mod cloudflare;
+mod debounce;
+pub mod desec;
+pub mod duckdns;
 pub mod dyndns;
+mod host;
+mod lockout;
+mod reconcile;
+pub mod record;
+pub mod rfc2136;
 pub mod update;
+mod verify;
+pub mod webhook;
 
-use signal_hook::{consts::TERM_SIGNALS, flag};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
-use crate::config::CONFIG;
+use crate::config::{API, CONFIG};
+use host::Host;
 
 pub fn launch() -> std::io::Result<()> {
-    match CONFIG.api {
-        crate::config::API::Update => update::Update::new().run(),
-        crate::config::API::DynDNS => dyndns::DynDNS::new().run(),
+    if CONFIG.hostname.is_empty() {
+        panic!("missing field `hostname`");
     }
+    let provider: Box<dyn Provider> = match CONFIG.api {
+        API::Update => Box::new(update::Update::new()),
+        API::DynDNS => Box::new(dyndns::DynDNS::new()),
+        API::Record => Box::new(record::Records::new()),
+        API::RFC2136 => Box::new(rfc2136::Rfc2136::new()),
+        API::Cloudflare => Box::new(cloudflare::Cloudflare::new()),
+        API::Webhook => Box::new(webhook::Webhook::new()),
+        API::DuckDNS => Box::new(duckdns::DuckDns::new()),
+        API::DeSEC => Box::new(desec::DeSec::new()),
+    };
+    for option in CONFIG.options.set() {
+        if !provider.supports(option) {
+            warn!(
+                "options.{option} isn't supported by {:?}, ignored",
+                CONFIG.api
+            );
+        }
+    }
+    Host::new(provider).run()
 }
 
-pub trait API {
-    fn new() -> Self;
-    fn check_v4(&mut self);
-    fn check_v6(&mut self);
-    fn update(&mut self);
-    fn run(&mut self) -> std::io::Result<()> {
-        let term_now = Arc::new(AtomicBool::new(false));
-        for sig in TERM_SIGNALS {
-            flag::register_conditional_shutdown(*sig, 1, Arc::clone(&term_now))?;
-            flag::register(*sig, Arc::clone(&term_now))?;
-        }
+pub enum Outcome {
+    Success,
+    // network errors, 5xx, server side dns errors
+    Transient(String),
+    // bad credentials, unknown host, abuse
+    Permanent(String),
+}
 
-        while !term_now.load(Ordering::Relaxed) {
-            if !CONFIG.no_ipv4 {
-                self.check_v4();
-            }
-            if !CONFIG.no_ipv6 {
-                self.check_v6();
-            }
-            self.update();
-            std::thread::sleep(Duration::from_secs_f64(CONFIG.interval));
-        }
-        info!("gracefully shutting down");
-        Ok(())
+/// A DNS service the host's addresses are published to. Detection,
+/// debouncing, state, retries and lockout are handled by `Host`.
+pub trait Provider {
+    /// Publishes the pending addresses, `None` for a family that is
+    /// unchanged or disabled.
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome;
+
+    /// The names whose records `publish` changes.
+    fn names(&self) -> Vec<String> {
+        vec![CONFIG.hostname.clone()]
+    }
+
+    /// Whether the record option `option` (see `config::Options`) is
+    /// carried into the published records.
+    fn supports(&self, _option: &str) -> bool {
+        false
+    }
+
+    /// Whether `names` resolve to the published addresses, not the case
+    /// for records behind a proxy.
+    fn resolvable(&self) -> bool {
+        true
     }
 }
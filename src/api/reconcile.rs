@@ -0,0 +1,110 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use crate::config::{Source, CONFIG};
+use crate::dns;
+use crate::filter::Cidr;
+use crate::rest::Rest;
+use crate::Error;
+
+// the local state files only say what was sent, not what is served;
+// this compares them with the live records now and then
+pub struct Reconcile {
+    last: Option<Instant>,
+    servers: Vec<String>,
+}
+
+impl Reconcile {
+    pub fn new() -> Self {
+        Reconcile {
+            last: None,
+            servers: CONFIG.reconcile.nameservers.clone(),
+        }
+    }
+
+    pub fn due(&mut self) -> bool {
+        let interval = Duration::from_secs_f64(CONFIG.reconcile.interval);
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return false;
+        }
+        self.last = Some(Instant::now());
+        true
+    }
+
+    fn served_dns(&mut self, name: &str, ip: IpAddr) -> Result<Vec<IpAddr>, Error> {
+        if self.servers.is_empty() {
+            self.servers = dns::nameservers(name)?;
+        }
+        let kind = if ip.is_ipv4() {
+            dns::TYPE_A
+        } else {
+            dns::TYPE_AAAA
+        };
+        // the first nameserver that answers is enough to notice a drift
+        let mut last = None;
+        for server in self.servers.iter() {
+            match dns::query(server, name, kind, false) {
+                Ok(answers) => {
+                    return Ok(answers
+                        .iter()
+                        .filter(|a| a.kind == kind)
+                        .filter_map(|a| a.data.parse().ok())
+                        .collect())
+                }
+                Err(err) => last = Some(err),
+            }
+        }
+        Err(last.unwrap_or_else(|| Error::Other(format!("no nameservers for {name}"))))
+    }
+
+    fn served_rest(&self, name: &str, ip: IpAddr) -> Result<Vec<IpAddr>, Error> {
+        let rest = Rest::new();
+        let zone = rest.zone_by_name(&CONFIG.hostname)?;
+        // the apex addresses are part of the zone, not records, and for
+        // ipv6 only the prefix is kept
+        if name == zone.name {
+            let served = match ip {
+                IpAddr::V4(_) => zone.ipv4address.parse().ok(),
+                IpAddr::V6(_) => match zone.ipv6prefix.parse::<Cidr>() {
+                    Ok(prefix) if prefix.contains(&ip) => Some(ip),
+                    Ok(prefix) => Some(prefix.addr()),
+                    Err(_) => None,
+                },
+            };
+            return Ok(served.into_iter().collect());
+        }
+        let kind = if ip.is_ipv4() { "A" } else { "AAAA" };
+        let relative = name
+            .strip_suffix(&format!(".{}", zone.name))
+            .unwrap_or(name);
+        Ok(rest
+            .records(zone.id)?
+            .iter()
+            .filter(|r| r.name == relative && r.kind.eq_ignore_ascii_case(kind))
+            .filter_map(|r| r.data.parse().ok())
+            .collect())
+    }
+
+    /// Whether any of `names` serves something other than `ip`.
+    pub fn drifted(&mut self, names: &[String], ip: IpAddr) -> bool {
+        for name in names {
+            let served = match CONFIG.reconcile.source {
+                Source::Dns => self.served_dns(name, ip),
+                Source::Rest => self.served_rest(name, ip),
+            };
+            match served {
+                Ok(served) if served.contains(&ip) => debug!("{name} serves {ip}"),
+                Ok(served) => {
+                    let served: Vec<String> = served.iter().map(|ip| ip.to_string()).collect();
+                    warn!(
+                        "{name} serves [{}] instead of {ip}, updating",
+                        served.join(", ")
+                    );
+                    return true;
+                }
+                Err(err) => warn!("can't reconcile {name}: {err}"),
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,124 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::api::{Outcome, Provider};
+use crate::config::CONFIG;
+use crate::rest::{NewRecord, Rest};
+use crate::Error;
+
+// `nas`, `nas.home.dynv6.net` and `@` for the apex of `home.dynv6.net`
+fn relative_name(name: &str) -> String {
+    let zone = CONFIG.hostname.trim_end_matches('.');
+    let name = name.trim().trim_end_matches('.');
+    if name == "@" || name == zone {
+        return String::new();
+    }
+    match name.strip_suffix(zone) {
+        Some(sub) if sub.ends_with('.') => sub.trim_end_matches('.').to_string(),
+        _ => name.to_string(),
+    }
+}
+
+fn fqdn(name: &str) -> String {
+    if name.is_empty() {
+        return CONFIG.hostname.clone();
+    }
+    format!("{name}.{}", CONFIG.hostname)
+}
+
+pub struct Records {
+    rest: Rest,
+    zone: Option<u64>,
+    names: Vec<String>,
+}
+
+impl Records {
+    pub fn new() -> Self {
+        Records {
+            rest: Rest::new(),
+            zone: None,
+            names: CONFIG
+                .record
+                .names
+                .iter()
+                .map(|n| relative_name(n))
+                .collect(),
+        }
+    }
+
+    fn zone(&mut self) -> Result<u64, Error> {
+        if let Some(zone) = self.zone {
+            return Ok(zone);
+        }
+        let zone = self.rest.zone_by_name(&CONFIG.hostname)?.id;
+        self.zone = Some(zone);
+        Ok(zone)
+    }
+
+    // only records whose data differs are patched
+    fn apply(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Result<Outcome, Error> {
+        let zone = self.zone()?;
+        let records = self.rest.records(zone)?;
+        let mut wanted: Vec<(&str, IpAddr)> = Vec::new();
+        if let Some(v4) = v4 {
+            wanted.push(("A", IpAddr::V4(v4)));
+        }
+        if let Some(v6) = v6 {
+            wanted.push(("AAAA", IpAddr::V6(v6)));
+        }
+        let mut changes = Vec::new();
+        for name in self.names.iter() {
+            for (kind, ip) in wanted.iter() {
+                let record = records
+                    .iter()
+                    .find(|r| &r.name == name && r.kind.eq_ignore_ascii_case(kind));
+                match record {
+                    Some(r) if r.data.parse::<IpAddr>().ok() == Some(*ip) => {
+                        debug!("{kind} {} is already {ip}", fqdn(name));
+                    }
+                    Some(r) => changes.push((name, *kind, *ip, Some(r.id))),
+                    None if CONFIG.record.create => changes.push((name, *kind, *ip, None)),
+                    None => {
+                        return Ok(Outcome::Permanent(format!(
+                            "{kind} record {} not found and record.create is disabled",
+                            fqdn(name)
+                        )));
+                    }
+                }
+            }
+        }
+        for (name, kind, ip, id) in changes {
+            let data = ip.to_string();
+            let record = NewRecord {
+                kind,
+                name,
+                data: &data,
+                priority: None,
+            };
+            match id {
+                Some(id) => {
+                    self.rest.update_record(zone, id, &record)?;
+                    info!("updated {kind} {}: {data}", fqdn(name));
+                }
+                None => {
+                    self.rest.create_record(zone, &record)?;
+                    info!("created {kind} {}: {data}", fqdn(name));
+                }
+            }
+        }
+        Ok(Outcome::Success)
+    }
+}
+
+impl Provider for Records {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        match self.apply(v4, v6) {
+            Ok(outcome) => outcome,
+            Err(err) if err.is_transient() => Outcome::Transient(err.to_string()),
+            Err(err) => Outcome::Permanent(err.to_string()),
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.names.iter().map(|name| fqdn(name)).collect()
+    }
+}
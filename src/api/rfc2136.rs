@@ -0,0 +1,88 @@
+use base64::{engine::general_purpose, Engine as _};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::api::{Outcome, Provider};
+use crate::config::CONFIG;
+use crate::dns::{self, Rr, Tsig};
+use crate::Error;
+
+// NOTAUTH, BADSIG, BADKEY, ... won't go away by sending the same update again
+fn is_fatal(rcode: u16) -> bool {
+    !matches!(rcode, 2 | 18)
+}
+
+pub struct Rfc2136 {
+    tsig: Tsig,
+}
+
+impl Rfc2136 {
+    pub fn new() -> Self {
+        let secret = general_purpose::STANDARD
+            .decode(CONFIG.rfc2136.secret.trim())
+            .expect("rfc2136.secret must be base64");
+        Rfc2136 {
+            tsig: Tsig {
+                key: CONFIG.rfc2136.key.clone(),
+                algorithm: CONFIG.rfc2136.algorithm,
+                secret,
+            },
+        }
+    }
+
+    // replaces the whole RRset of each pending family in one message,
+    // so the server applies both or neither
+    fn send(&self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Result<Outcome, Error> {
+        let config = &CONFIG.rfc2136;
+        let host = &CONFIG.hostname;
+        let zone = config.zone.as_deref().unwrap_or(host);
+        let ttl = CONFIG.options.ttl.unwrap_or(config.ttl);
+        let mut updates = Vec::new();
+        if let Some(v4) = v4 {
+            updates.push(Rr::delete(host, dns::TYPE_A));
+            updates.push(Rr::add(host, dns::TYPE_A, ttl, v4.octets().to_vec()));
+        }
+        if let Some(v6) = v6 {
+            updates.push(Rr::delete(host, dns::TYPE_AAAA));
+            updates.push(Rr::add(host, dns::TYPE_AAAA, ttl, v6.octets().to_vec()));
+        }
+        let mut msg = dns::update(zone, &updates);
+        let mac = self.tsig.sign(&mut msg);
+        let response = dns::exchange(&config.server, &msg)?;
+
+        let rcode = dns::rcode(&response);
+        let error = match self.tsig.verify(&response, &mac) {
+            Ok(error) => error,
+            // an unsigned REFUSED or NOTAUTH is still worth reporting as such
+            Err(_) if rcode != 0 => 0,
+            Err(err) => return Ok(Outcome::Transient(err.to_string())),
+        };
+        let rcode = if error != 0 { error } else { rcode };
+        if rcode == 0 {
+            info!("{host} updated on {}", config.server);
+            return Ok(Outcome::Success);
+        }
+        let msg = format!(
+            "{} rejected the update: {}",
+            config.server,
+            dns::rcode_name(rcode)
+        );
+        if is_fatal(rcode) {
+            Ok(Outcome::Permanent(msg))
+        } else {
+            Ok(Outcome::Transient(msg))
+        }
+    }
+}
+
+impl Provider for Rfc2136 {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        match self.send(v4, v6) {
+            Ok(outcome) => outcome,
+            Err(err) => Outcome::Transient(err.to_string()),
+        }
+    }
+
+    fn supports(&self, option: &str) -> bool {
+        option == "ttl"
+    }
+}
@@ -1,21 +1,45 @@
 use serde::Serialize;
-use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-use crate::api::API;
-use crate::config::{CONFIG, IPV4_FILE, IPV6_FILE};
-use crate::util;
-use crate::CLIENT;
+use crate::api::{Outcome, Provider};
+use crate::config::CONFIG;
+use crate::filter::Cidr;
+use crate::requests::Response;
+use crate::{Error, CLIENT};
 
-const DYNV6_URL: &'static str = "https://dynv6.com/api/update";
+const DYNV6_URL: &str = "https://dynv6.com/api/update";
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Updated,
+    Unchanged,
+    InvalidToken,
+    ZoneNotFound,
+    Error(String),
+}
+
+impl From<&str> for Reply {
+    fn from(text: &str) -> Self {
+        match text.trim().to_lowercase().as_str() {
+            "addresses updated" => Reply::Updated,
+            "addresses unchanged" => Reply::Unchanged,
+            "invalid authentication token" => Reply::InvalidToken,
+            "zone not found" => Reply::ZoneNotFound,
+            _ => Reply::Error(text.trim().to_string()),
+        }
+    }
+}
 
 #[derive(Serialize)]
 struct Params {
     hostname: &'static str,
     token: &'static str,
     #[serde(rename = "ipv4", skip_serializing_if = "Option::is_none")]
-    v4: Option<String>,
+    v4: Option<Ipv4Addr>,
     #[serde(rename = "ipv6", skip_serializing_if = "Option::is_none")]
-    v6: Option<String>,
+    v6: Option<Ipv6Addr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ipv6prefix: Option<Cidr>,
 }
 
 impl Params {
@@ -25,68 +49,122 @@ impl Params {
             token: &CONFIG.token,
             v4: None,
             v6: None,
+            ipv6prefix: None,
         }
     }
 }
 
 pub struct Update {
-    v4: String,
-    v6: String,
     params: Params,
+    url: String,
 }
 
-impl API for Update {
-    fn new() -> Self {
+impl Update {
+    pub fn new() -> Self {
         Update {
-            v4: CONFIG.current_ip.v4.clone(),
-            v6: CONFIG.current_ip.v6.clone(),
             params: Params::new(),
+            url: DYNV6_URL.to_string(),
         }
     }
-    fn check_v4(&mut self) {
-        debug!("check v4");
-        if let Some(new_v4) = util::ipv4() {
-            let new_v4 = new_v4.to_string();
-            if new_v4 != self.v4 {
-                info!("old ipv4: {}, current ipv4: {}", self.v4, new_v4);
-                self.params.v4 = Some(new_v4);
+}
+
+fn outcome(res: Result<Response, Error>) -> Outcome {
+    match res {
+        Ok(res) => {
+            let status = res.status();
+            let text = match res.text() {
+                Ok(text) => text.trim().to_string(),
+                Err(err) => format!("{err:?}"),
+            };
+            // dynv6 may answer 200 with an error message in the body
+            match Reply::from(text.as_str()) {
+                Reply::Updated | Reply::Unchanged if status.is_success() => {
+                    info!("{text}");
+                    Outcome::Success
+                }
+                Reply::InvalidToken | Reply::ZoneNotFound => Outcome::Permanent(text),
+                _ if status.is_success() || status.is_transient() => {
+                    Outcome::Transient(format!("code: {status}, msg: {text}"))
+                }
+                _ => Outcome::Permanent(format!("code: {status}, msg: {text}")),
             }
         }
+        Err(err) => Outcome::Transient(err.to_string()),
     }
-    fn check_v6(&mut self) {
-        debug!("check v6");
-        if let Some(new_v6) = util::ipv6() {
-            let new_v6 = new_v6.to_string();
-            if new_v6 != self.v6 {
-                info!("old ipv6: {}, current ipv6: {}", self.v6, new_v6);
-                self.params.v6 = Some(new_v6);
-            }
-        }
+}
+
+impl Provider for Update {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        self.params.v4 = v4;
+        self.params.v6 = v6;
+        self.params.ipv6prefix = v6
+            .zip(CONFIG.update.prefix)
+            .map(|(v6, len)| Cidr::network(IpAddr::V6(v6), len));
+        outcome(CLIENT.get(&self.url).query(&self.params).send())
     }
-    fn update(&mut self) {
-        if self.params.v4.is_none() && self.params.v6.is_none() {
-            return;
-        }
-        info!("ipv4/ipv6 address changed, start update");
-        match CLIENT.get(DYNV6_URL).query(&self.params).send() {
-            Ok(res) => {
-                if res.status().is_success() {
-                    info!("{:?}", res.text());
-                    if let Some(v4) = &self.params.v4 {
-                        fs::write(IPV4_FILE, v4).ok();
-                        self.v4 = v4.to_owned();
-                    }
-                    if let Some(v6) = &self.params.v6 {
-                        fs::write(IPV6_FILE, v6).ok();
-                        self.v6 = v6.to_owned();
-                    }
-                } else {
-                    error!("code: {}, msg: {:?}", res.status(), res.text());
-                }
-            }
-            Err(err) => error!("{err}"),
-        }
-        self.params.v4 = None;
-        self.params.v6 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+
+    fn publish(status: u16, body: &str) -> Outcome {
+        let (url, _) = serve(status, body);
+        outcome(CLIENT.get(&url).send())
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(Reply::from("addresses updated"), Reply::Updated);
+        assert_eq!(Reply::from("Addresses unchanged\n"), Reply::Unchanged);
+        assert_eq!(
+            Reply::from("invalid authentication token"),
+            Reply::InvalidToken
+        );
+        assert_eq!(Reply::from("zone not found"), Reply::ZoneNotFound);
+        assert_eq!(Reply::from(" what "), Reply::Error("what".to_string()));
+    }
+
+    #[test]
+    fn outcomes() {
+        assert!(matches!(
+            publish(200, "addresses updated"),
+            Outcome::Success
+        ));
+        assert!(matches!(
+            publish(200, "addresses unchanged"),
+            Outcome::Success
+        ));
+        // a 200 with an error must neither be saved nor retried
+        assert!(matches!(
+            publish(200, "invalid authentication token"),
+            Outcome::Permanent(_)
+        ));
+        assert!(matches!(
+            publish(404, "zone not found"),
+            Outcome::Permanent(_)
+        ));
+        assert!(matches!(publish(200, "what"), Outcome::Transient(_)));
+        assert!(matches!(
+            publish(503, "addresses updated"),
+            Outcome::Transient(_)
+        ));
+        assert!(matches!(publish(400, "bad request"), Outcome::Permanent(_)));
+        let refused = CLIENT.get("http://127.0.0.1:1/").send();
+        assert!(matches!(outcome(refused), Outcome::Transient(_)));
+    }
+
+    #[test]
+    fn request() {
+        let (url, rx) = serve(200, "addresses updated");
+        let mut update = Update {
+            params: Params::new(),
+            url,
+        };
+        let v4 = "192.0.2.1".parse().unwrap();
+        assert!(matches!(update.publish(Some(v4), None), Outcome::Success));
+        let request = rx.recv().unwrap();
+        assert!(request.contains("&token=test&ipv4=192.0.2.1 "));
     }
 }
@@ -0,0 +1,75 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use crate::config::CONFIG;
+use crate::dns;
+
+pub enum Status {
+    Propagated,
+    Pending,
+    // accepted by the provider, but not served by every nameserver in time
+    NotPropagated(String),
+}
+
+// an update the provider accepted, checked against the authoritative
+// nameservers once per cycle until all of them serve it
+pub struct Verify {
+    names: Vec<String>,
+    wanted: Vec<(u16, IpAddr)>,
+    servers: Vec<String>,
+    deadline: Instant,
+}
+
+impl Verify {
+    pub fn new(names: Vec<String>, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Self {
+        let mut wanted = Vec::new();
+        if let Some(v4) = v4 {
+            wanted.push((dns::TYPE_A, IpAddr::V4(v4)));
+        }
+        if let Some(v6) = v6 {
+            wanted.push((dns::TYPE_AAAA, IpAddr::V6(v6)));
+        }
+        Verify {
+            names,
+            wanted,
+            servers: CONFIG.verify.nameservers.clone(),
+            deadline: Instant::now() + Duration::from_secs_f64(CONFIG.verify.timeout),
+        }
+    }
+
+    fn pending(&self, missing: String) -> Status {
+        if Instant::now() >= self.deadline {
+            return Status::NotPropagated(missing);
+        }
+        debug!("not propagated yet: {missing}");
+        Status::Pending
+    }
+
+    pub fn check(&mut self) -> Status {
+        if self.servers.is_empty() {
+            match dns::nameservers(&self.names[0]) {
+                Ok(servers) => self.servers = servers,
+                Err(err) => return self.pending(err.to_string()),
+            }
+        }
+        let mut missing = Vec::new();
+        for name in self.names.iter() {
+            for (kind, ip) in self.wanted.iter() {
+                for server in self.servers.iter() {
+                    match dns::query(server, name, *kind, false) {
+                        Ok(answers)
+                            if answers.iter().any(|a| {
+                                a.kind == *kind && a.data.parse::<IpAddr>().ok() == Some(*ip)
+                            }) => {}
+                        Ok(_) => missing.push(format!("{name} {ip} on {server}")),
+                        Err(err) => missing.push(format!("{name} on {server}: {err}")),
+                    }
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Status::Propagated;
+        }
+        self.pending(missing.join(", "))
+    }
+}
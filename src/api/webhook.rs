@@ -0,0 +1,175 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use regex::Regex;
+
+use crate::api::{Outcome, Provider};
+use crate::config::{CONFIG, IPV4_FILE, IPV6_FILE};
+use crate::state;
+use crate::CLIENT;
+
+// `2001:db8:1:2::/64` for `2001:db8:1:2:3:4:5:6`
+fn prefix(v6: Ipv6Addr) -> String {
+    let network = u128::from(v6) & (u128::MAX << 64);
+    format!("{}/64", Ipv6Addr::from(network))
+}
+
+// everything but the unreserved characters of RFC 3986
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+// values put into the url are percent-encoded
+fn render(template: &str, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>, url: bool) -> String {
+    let options = &CONFIG.options;
+    let values = [
+        ("{hostname}", CONFIG.hostname.clone()),
+        ("{ipv4}", v4.map(|ip| ip.to_string()).unwrap_or_default()),
+        ("{ipv6}", v6.map(|ip| ip.to_string()).unwrap_or_default()),
+        ("{prefix}", v6.map(prefix).unwrap_or_default()),
+        (
+            "{ttl}",
+            options.ttl.map(|t| t.to_string()).unwrap_or_default(),
+        ),
+        (
+            "{proxied}",
+            options.proxied.map(|p| p.to_string()).unwrap_or_default(),
+        ),
+        ("{comment}", options.comment.clone().unwrap_or_default()),
+    ];
+    values
+        .iter()
+        .fold(template.to_string(), |text, (placeholder, value)| {
+            if url {
+                text.replace(placeholder, &encode(value))
+            } else {
+                text.replace(placeholder, value)
+            }
+        })
+}
+
+pub struct Webhook {
+    regex: Option<Regex>,
+    // fill in the family that didn't change, an empty `{ipv4}` could
+    // clear the record on the other side
+    v4: Option<Ipv4Addr>,
+    v6: Option<Ipv6Addr>,
+}
+
+impl Webhook {
+    pub fn new() -> Self {
+        let regex = CONFIG.webhook.regex.as_ref().map(|regex| {
+            Regex::new(regex).unwrap_or_else(|e| panic!("invalid webhook.regex: {e}"))
+        });
+        Webhook {
+            regex,
+            v4: if CONFIG.no_ipv4 {
+                None
+            } else {
+                CONFIG.current_ip.v4.or_else(|| state::load(IPV4_FILE))
+            },
+            v6: if CONFIG.no_ipv6 {
+                None
+            } else {
+                CONFIG.current_ip.v6.or_else(|| state::load(IPV6_FILE))
+            },
+        }
+    }
+}
+
+impl Provider for Webhook {
+    fn publish(&mut self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Outcome {
+        let config = &CONFIG.webhook;
+        let (v4, v6) = (v4.or(self.v4), v6.or(self.v6));
+        let method = config.method.to_uppercase();
+        let url = render(&config.url, v4, v6, true);
+        debug!("{method} {url}");
+        let mut req = CLIENT.request(&method, &url);
+        for (name, value) in config.headers.iter() {
+            req = req.header(name, &render(value, v4, v6, false));
+        }
+        let res = if let Some(body) = &config.body {
+            req.send_string(&render(body, v4, v6, false))
+        } else if !config.form.is_empty() {
+            let form: Vec<(&str, String)> = config
+                .form
+                .iter()
+                .map(|(name, value)| (name.as_str(), render(value, v4, v6, false)))
+                .collect();
+            let form: Vec<(&str, &str)> = form.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            req.send_form(&form)
+        } else {
+            req.send()
+        };
+        match res {
+            Ok(res) => {
+                let status = res.status();
+                let text = match res.text() {
+                    Ok(text) => text.trim().to_string(),
+                    Err(err) => format!("{err:?}"),
+                };
+                let matched = self.regex.as_ref().is_none_or(|r| r.is_match(&text));
+                if config.status.contains(status.as_u16()) && matched {
+                    info!("{method} {url}: {status}");
+                    (self.v4, self.v6) = (v4, v6);
+                    Outcome::Success
+                } else if status.is_success() || status.is_transient() {
+                    Outcome::Transient(format!("code: {status}, msg: {text}"))
+                } else {
+                    Outcome::Permanent(format!("code: {status}, msg: {text}"))
+                }
+            }
+            Err(err) => Outcome::Transient(err.to_string()),
+        }
+    }
+
+    // only what the templates ask for is sent
+    fn supports(&self, option: &str) -> bool {
+        let config = &CONFIG.webhook;
+        let placeholder = format!("{{{option}}}");
+        config.url.contains(&placeholder)
+            || config.headers.values().any(|v| v.contains(&placeholder))
+            || config
+                .body
+                .as_ref()
+                .is_some_and(|b| b.contains(&placeholder))
+            || config.form.values().any(|v| v.contains(&placeholder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(encode("a-b.c_d~"), "a-b.c_d~");
+        assert_eq!(encode("2001:db8::/64"), "2001%3Adb8%3A%3A%2F64");
+        assert_eq!(encode("home & office"), "home%20%26%20office");
+    }
+
+    #[test]
+    fn templates() {
+        testing::init();
+        let v4 = "192.0.2.1".parse().ok();
+        let v6 = "2001:db8::1".parse().ok();
+        let template = "https://a.b/?a={ipv4}&aaaa={ipv6}&net={prefix}";
+        assert_eq!(
+            render(template, v4, v6, true),
+            "https://a.b/?a=192.0.2.1&aaaa=2001%3Adb8%3A%3A1&net=2001%3Adb8%3A%3A%2F64"
+        );
+        assert_eq!(
+            render(r#"{"aaaa": "{ipv6}"}"#, v4, v6, false),
+            r#"{"aaaa": "2001:db8::1"}"#
+        );
+        assert_eq!(render("a={ipv4}", None, v6, false), "a=");
+    }
+}
@@ -0,0 +1,130 @@
+use std::process::exit;
+
+use crate::acme;
+use crate::logger;
+use crate::rest::{NewRecord, Record, Rest};
+use crate::Error;
+
+const USAGE: &str = "usage:
+    dynv6                                           run the ddns daemon
+    dynv6 zones                                     list zones
+    dynv6 records <zone>                            list records of a zone
+    dynv6 record add <zone> <type> <name> <data>    create a record
+    dynv6 record update <zone> <id> <data>          change the data of a record
+    dynv6 record delete <zone> <id>                 delete a record
+    dynv6 acme present <domain> <token>             set the _acme-challenge TXT record
+                                                    and wait for the nameservers
+    dynv6 acme cleanup [<domain> [<token>]]         remove the _acme-challenge TXT record
+
+<zone> is either the zone name or its id, <name> is relative to the zone.
+Without arguments, the acme subcommands read CERTBOT_DOMAIN and
+CERTBOT_VALIDATION, so they work as certbot manual hooks.";
+
+pub fn run(args: &[String]) -> std::io::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    // `help` and usage errors must work without a configured token
+    let result = match args.as_slice() {
+        ["zones"] => zones(&rest()),
+        ["records", zone] => records(&rest(), zone),
+        ["record", "add", zone, kind, name, data] => add(&rest(), zone, kind, name, data),
+        ["record", "update", zone, id, data] => update(&rest(), zone, id, data),
+        ["record", "delete", zone, id] => delete(&rest(), zone, id),
+        ["acme", "present", domain, token] => acme::present(&rest(), domain, token),
+        ["acme", "present"] => certbot("CERTBOT_DOMAIN")
+            .and_then(|domain| acme::present(&rest(), &domain, &certbot("CERTBOT_VALIDATION")?)),
+        ["acme", "cleanup", domain, token] => acme::cleanup(&rest(), domain, Some(token)),
+        ["acme", "cleanup", domain] => acme::cleanup(&rest(), domain, None),
+        ["acme", "cleanup"] => certbot("CERTBOT_DOMAIN").and_then(|domain| {
+            let token = std::env::var("CERTBOT_VALIDATION").ok();
+            acme::cleanup(&rest(), &domain, token.as_deref())
+        }),
+        ["help" | "-h" | "--help"] => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("{err}");
+        exit(1);
+    }
+    Ok(())
+}
+
+// the api commands need the config, which also sets up logging
+fn rest() -> Rest {
+    logger::init_logger();
+    Rest::new()
+}
+
+fn certbot(var: &str) -> Result<String, Error> {
+    std::env::var(var).map_err(|_| Error::Other(format!("{var} is not set")))
+}
+
+fn zone_id(rest: &Rest, zone: &str) -> Result<u64, Error> {
+    match zone.parse() {
+        Ok(id) => Ok(id),
+        Err(_) => Ok(rest.zone_by_name(zone)?.id),
+    }
+}
+
+fn record_id(id: &str) -> Result<u64, Error> {
+    id.parse()
+        .map_err(|_| Error::Other(format!("invalid record id: {id}")))
+}
+
+fn print_record(record: &Record) {
+    println!(
+        "{}\t{}\t{}\t{}",
+        record.id, record.kind, record.name, record.data
+    );
+}
+
+fn zones(rest: &Rest) -> Result<(), Error> {
+    for zone in rest.zones()? {
+        println!(
+            "{}\t{}\t{}\t{}",
+            zone.id, zone.name, zone.ipv4address, zone.ipv6prefix
+        );
+    }
+    Ok(())
+}
+
+fn records(rest: &Rest, zone: &str) -> Result<(), Error> {
+    for record in rest.records(zone_id(rest, zone)?)? {
+        print_record(&record);
+    }
+    Ok(())
+}
+
+fn add(rest: &Rest, zone: &str, kind: &str, name: &str, data: &str) -> Result<(), Error> {
+    let kind = kind.to_uppercase();
+    let record = NewRecord {
+        kind: &kind,
+        name,
+        data,
+        priority: None,
+    };
+    print_record(&rest.create_record(zone_id(rest, zone)?, &record)?);
+    Ok(())
+}
+
+fn update(rest: &Rest, zone: &str, id: &str, data: &str) -> Result<(), Error> {
+    let zone = zone_id(rest, zone)?;
+    let current = rest.record(zone, record_id(id)?)?;
+    let record = NewRecord {
+        kind: &current.kind,
+        name: &current.name,
+        data,
+        priority: current.priority,
+    };
+    print_record(&rest.update_record(zone, current.id, &record)?);
+    Ok(())
+}
+
+fn delete(rest: &Rest, zone: &str, id: &str) -> Result<(), Error> {
+    rest.delete_record(zone_id(rest, zone)?, record_id(id)?)
+}
@@ -0,0 +1,732 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+
+use crate::config::Algorithm;
+use crate::Error;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_ANY: u16 = 255;
+
+const OPCODE_UPDATE: u16 = 5 << 11;
+const FLAG_QR: u16 = 1 << 15;
+const FLAG_TC: u16 = 1 << 9;
+const FLAG_RD: u16 = 1 << 8;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+// used when /etc/resolv.conf has no nameserver
+const FALLBACK_RESOLVER: &str = "1.1.1.1";
+// seconds of clock skew accepted by the server
+const FUDGE: u16 = 300;
+
+pub struct Rr {
+    pub name: String,
+    pub kind: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub rdata: Vec<u8>,
+}
+
+impl Rr {
+    /// Removes every record of `kind` at `name`.
+    pub fn delete(name: &str, kind: u16) -> Self {
+        Rr {
+            name: name.to_string(),
+            kind,
+            class: CLASS_ANY,
+            ttl: 0,
+            rdata: Vec::new(),
+        }
+    }
+
+    pub fn add(name: &str, kind: u16, ttl: u32, rdata: Vec<u8>) -> Self {
+        Rr {
+            name: name.to_string(),
+            kind,
+            class: CLASS_IN,
+            ttl,
+            rdata,
+        }
+    }
+}
+
+pub struct Answer {
+    pub kind: u16,
+    // the address, name or text, depending on `kind`
+    pub data: String,
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_be_bytes());
+    }
+
+    // uncompressed, lowercase is the canonical form required by TSIG
+    fn name(&mut self, name: &str) {
+        for label in name.trim_end_matches('.').split('.') {
+            if label.is_empty() {
+                continue;
+            }
+            self.0.push(label.len() as u8);
+            self.0.extend(label.bytes().map(|b| b.to_ascii_lowercase()));
+        }
+        self.0.push(0);
+    }
+
+    fn rr(&mut self, rr: &Rr) {
+        self.name(&rr.name);
+        self.u16(rr.kind);
+        self.u16(rr.class);
+        self.u32(rr.ttl);
+        self.u16(rr.rdata.len() as u16);
+        self.0.extend_from_slice(&rr.rdata);
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos + len;
+        let bytes = self
+            .buf
+            .get(self.pos..end)
+            .ok_or_else(|| Error::Other("truncated dns message".to_string()))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // follows compression pointers, the position ends after the first one
+    fn name(&mut self) -> Result<String, Error> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut end = None;
+        for _ in 0..128 {
+            let len = *self
+                .buf
+                .get(pos)
+                .ok_or_else(|| Error::Other("truncated dns name".to_string()))?
+                as usize;
+            if len & 0xc0 == 0xc0 {
+                let low = *self
+                    .buf
+                    .get(pos + 1)
+                    .ok_or_else(|| Error::Other("truncated dns name".to_string()))?;
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | low as usize;
+                continue;
+            }
+            if len == 0 {
+                self.pos = end.unwrap_or(pos + 1);
+                return Ok(labels.join("."));
+            }
+            let label = self
+                .buf
+                .get(pos + 1..pos + 1 + len)
+                .ok_or_else(|| Error::Other("truncated dns name".to_string()))?;
+            labels.push(String::from_utf8_lossy(label).to_lowercase());
+            pos += 1 + len;
+        }
+        Err(Error::Other("dns name loop".to_string()))
+    }
+
+    fn skip_rr(&mut self) -> Result<(), Error> {
+        self.name()?;
+        self.bytes(8)?;
+        let len = self.u16()? as usize;
+        self.bytes(len)?;
+        Ok(())
+    }
+}
+
+/// Builds an UPDATE message for `zone` with the given update section.
+pub fn update(zone: &str, updates: &[Rr]) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u16(fastrand::u16(..));
+    w.u16(OPCODE_UPDATE);
+    w.u16(1); // zone
+    w.u16(0); // prerequisites
+    w.u16(updates.len() as u16);
+    w.u16(0); // additional
+    w.name(zone);
+    w.u16(TYPE_SOA);
+    w.u16(CLASS_IN);
+    for rr in updates {
+        w.rr(rr);
+    }
+    w.0
+}
+
+/// Asks `server` for the `kind` records of `name`. Recursion is only
+/// wanted from resolvers, authoritative servers answer from their zone.
+pub fn query(server: &str, name: &str, kind: u16, recursive: bool) -> Result<Vec<Answer>, Error> {
+    let mut w = Writer::default();
+    w.u16(fastrand::u16(..));
+    w.u16(if recursive { FLAG_RD } else { 0 });
+    w.u16(1); // question
+    w.u16(0);
+    w.u16(0);
+    w.u16(0);
+    w.name(name);
+    w.u16(kind);
+    w.u16(CLASS_IN);
+    let response = exchange(server, &w.0)?;
+    match rcode(&response) {
+        0 => {}
+        3 => return Ok(Vec::new()),
+        rcode => return Err(Error::Other(format!("{server}: {}", rcode_name(rcode)))),
+    }
+
+    let mut r = Reader {
+        buf: &response,
+        pos: 12,
+    };
+    let questions = u16::from_be_bytes([response[4], response[5]]);
+    let answers = u16::from_be_bytes([response[6], response[7]]);
+    for _ in 0..questions {
+        r.name()?;
+        r.bytes(4)?;
+    }
+    let mut list = Vec::new();
+    for _ in 0..answers {
+        r.name()?;
+        let kind = r.u16()?;
+        r.bytes(6)?;
+        let len = r.u16()? as usize;
+        let end = r.pos + len;
+        let data = match kind {
+            TYPE_A if len == 4 => {
+                let b: [u8; 4] = r.bytes(4)?.try_into().unwrap();
+                Ipv4Addr::from(b).to_string()
+            }
+            TYPE_AAAA if len == 16 => {
+                let b: [u8; 16] = r.bytes(16)?.try_into().unwrap();
+                Ipv6Addr::from(b).to_string()
+            }
+            TYPE_NS => r.name()?,
+            // one or more character strings, joined like resolvers do
+            TYPE_TXT => {
+                let mut text = Vec::new();
+                while r.pos < end {
+                    let len = r.bytes(1)?[0] as usize;
+                    text.extend_from_slice(r.bytes(len)?);
+                }
+                String::from_utf8_lossy(&text).to_string()
+            }
+            _ => String::new(),
+        };
+        r.pos = end;
+        list.push(Answer { kind, data });
+    }
+    Ok(list)
+}
+
+/// The first nameserver in /etc/resolv.conf.
+pub fn resolver() -> String {
+    std::fs::read_to_string("/etc/resolv.conf")
+        .ok()
+        .and_then(|conf| {
+            conf.lines()
+                .filter_map(|line| line.trim().strip_prefix("nameserver"))
+                .map(|server| server.trim().to_string())
+                .find(|server| !server.is_empty())
+        })
+        .unwrap_or_else(|| FALLBACK_RESOLVER.to_string())
+}
+
+/// The authoritative nameservers of `name`, from the NS records of the
+/// closest enclosing zone.
+pub fn nameservers(name: &str) -> Result<Vec<String>, Error> {
+    let resolver = resolver();
+    let mut zone = name.trim_end_matches('.');
+    loop {
+        let servers: Vec<String> = query(&resolver, zone, TYPE_NS, true)?
+            .into_iter()
+            .filter(|answer| answer.kind == TYPE_NS)
+            .map(|answer| answer.data)
+            .collect();
+        if !servers.is_empty() {
+            return Ok(servers);
+        }
+        match zone.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => zone = parent,
+            _ => return Err(Error::Other(format!("no nameservers found for {name}"))),
+        }
+    }
+}
+
+pub fn rcode(response: &[u8]) -> u16 {
+    response.get(3).map_or(0, |b| (b & 0x0f) as u16)
+}
+
+pub fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        16 => "BADSIG".to_string(),
+        17 => "BADKEY".to_string(),
+        18 => "BADTIME".to_string(),
+        _ => format!("RCODE{rcode}"),
+    }
+}
+
+pub struct Tsig {
+    pub key: String,
+    pub algorithm: Algorithm,
+    pub secret: Vec<u8>,
+}
+
+impl Tsig {
+    fn mac(&self, parts: &[&[u8]]) -> Vec<u8> {
+        fn sign<M: Mac + hmac::digest::KeyInit>(secret: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+            let mut mac = <M as Mac>::new_from_slice(secret).expect("hmac accepts any key");
+            for part in parts {
+                mac.update(part);
+            }
+            mac.finalize().into_bytes().to_vec()
+        }
+        match self.algorithm {
+            Algorithm::HmacSha256 => sign::<Hmac<Sha256>>(&self.secret, parts),
+            Algorithm::HmacSha512 => sign::<Hmac<Sha512>>(&self.secret, parts),
+        }
+    }
+
+    // key name, class, ttl, algorithm, time signed and fudge
+    fn variables(&self, time: u64, fudge: u16, error: u16, other: &[u8]) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.name(&self.key);
+        w.u16(CLASS_ANY);
+        w.u32(0);
+        w.name(self.algorithm.name());
+        w.u16((time >> 32) as u16);
+        w.u32(time as u32);
+        w.u16(fudge);
+        w.u16(error);
+        w.u16(other.len() as u16);
+        w.0.extend_from_slice(other);
+        w.0
+    }
+
+    /// Appends a TSIG record to `msg` and returns its MAC, which is
+    /// needed to verify the response.
+    pub fn sign(&self, msg: &mut Vec<u8>) -> Vec<u8> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mac = self.mac(&[msg, &self.variables(time, FUDGE, 0, &[])]);
+
+        let mut rdata = Writer::default();
+        rdata.name(self.algorithm.name());
+        rdata.u16((time >> 32) as u16);
+        rdata.u32(time as u32);
+        rdata.u16(FUDGE);
+        rdata.u16(mac.len() as u16);
+        rdata.0.extend_from_slice(&mac);
+        rdata.0.extend_from_slice(&msg[0..2]);
+        rdata.u16(0);
+        rdata.u16(0);
+
+        let mut w = Writer(std::mem::take(msg));
+        w.rr(&Rr {
+            name: self.key.clone(),
+            kind: TYPE_TSIG,
+            class: CLASS_ANY,
+            ttl: 0,
+            rdata: rdata.0,
+        });
+        *msg = w.0;
+        let additional = u16::from_be_bytes([msg[10], msg[11]]) + 1;
+        msg[10..12].copy_from_slice(&additional.to_be_bytes());
+        mac
+    }
+
+    /// Checks the TSIG record of a response against the MAC of the
+    /// request. Returns the TSIG error code, which the server sets
+    /// instead of signing when it can't verify the request.
+    pub fn verify(&self, response: &[u8], request_mac: &[u8]) -> Result<u16, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.verify_at(response, request_mac, now)
+    }
+
+    fn verify_at(&self, response: &[u8], request_mac: &[u8], now: u64) -> Result<u16, Error> {
+        let mut r = Reader {
+            buf: response,
+            pos: 12,
+        };
+        let counts: Vec<u16> = (0..4)
+            .map(|i| u16::from_be_bytes([response[4 + i * 2], response[5 + i * 2]]))
+            .collect();
+        for _ in 0..counts[0] {
+            r.name()?;
+            r.bytes(4)?;
+        }
+        let records = counts[1] as usize + counts[2] as usize + counts[3] as usize;
+        if records == 0 || counts[3] == 0 {
+            return Err(Error::Other("response is not signed".to_string()));
+        }
+        for _ in 0..records - 1 {
+            r.skip_rr()?;
+        }
+
+        let start = r.pos;
+        let key = r.name()?;
+        let kind = r.u16()?;
+        r.bytes(6)?;
+        r.u16()?;
+        if kind != TYPE_TSIG {
+            return Err(Error::Other("response is not signed".to_string()));
+        }
+        if key != self.key.trim_end_matches('.').to_lowercase() {
+            return Err(Error::Other(format!(
+                "response signed with unknown key {key}"
+            )));
+        }
+        r.name()?;
+        let time = (r.u16()? as u64) << 32 | r.u32()? as u64;
+        let fudge = r.u16()?;
+        let len = r.u16()? as usize;
+        let mac = r.bytes(len)?;
+        let id = r.bytes(2)?;
+        let error = r.u16()?;
+        let other_len = r.u16()? as usize;
+        let other = r.bytes(other_len)?;
+        if error != 0 && mac.is_empty() {
+            return Ok(error);
+        }
+
+        // the message as it was before the server added its signature
+        let mut unsigned = response[..start].to_vec();
+        unsigned[0..2].copy_from_slice(id);
+        unsigned[10..12].copy_from_slice(&(counts[3] - 1).to_be_bytes());
+        let expected = self.mac(&[
+            &(request_mac.len() as u16).to_be_bytes(),
+            request_mac,
+            &unsigned,
+            &self.variables(time, fudge, error, other),
+        ]);
+        if expected != mac {
+            return Err(Error::Other("response signature mismatch".to_string()));
+        }
+        // a signed response captured earlier must not be replayed
+        if now.abs_diff(time) > fudge as u64 {
+            return Err(Error::Other(format!(
+                "response signed at {time}, more than {fudge}s from now"
+            )));
+        }
+        Ok(error)
+    }
+}
+
+fn resolve(server: &str) -> Result<SocketAddr, Error> {
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    let server = match server.parse::<SocketAddr>() {
+        Ok(_) => server.to_string(),
+        Err(_) if server.contains(':') => server.to_string(),
+        Err(_) => format!("{server}:53"),
+    };
+    server
+        .to_socket_addrs()
+        .map_err(|e| Error::Other(format!("{server}: {e}")))?
+        .next()
+        .ok_or_else(|| Error::Other(format!("{server}: no address")))
+}
+
+fn udp(server: SocketAddr, msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    let bind = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(msg)?;
+    let mut buf = vec![0; 4096];
+    loop {
+        let len = socket.recv(&mut buf)?;
+        // ignore stray datagrams not answering this request
+        if len >= 12 && buf[0..2] == msg[0..2] {
+            buf.truncate(len);
+            return Ok(buf);
+        }
+    }
+}
+
+fn tcp(server: SocketAddr, msg: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&server, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(&(msg.len() as u16).to_be_bytes())?;
+    stream.write_all(msg)?;
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Sends `msg` over UDP and retries over TCP when the response is
+/// truncated or the message is too large for a datagram.
+pub fn exchange(server: &str, msg: &[u8]) -> Result<Vec<u8>, Error> {
+    let addr = resolve(server)?;
+    if msg.len() <= 512 {
+        let response = udp(addr, msg).map_err(|e| Error::Other(format!("{server}: {e}")))?;
+        let flags = u16::from_be_bytes([response[2], response[3]]);
+        if flags & FLAG_TC == 0 {
+            return check(response);
+        }
+        debug!("response from {server} truncated, retrying over tcp");
+    }
+    let response = tcp(addr, msg).map_err(|e| Error::Other(format!("{server}: {e}")))?;
+    check(response)
+}
+
+fn check(response: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if response.len() < 12 || u16::from_be_bytes([response[2], response[3]]) & FLAG_QR == 0 {
+        return Err(Error::Other("invalid dns response".to_string()));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TIME: u64 = 1700000000;
+    // HMAC-SHA256 over the message below, computed independently
+    const REQUEST_MAC: &str = "56a6fc82cc00e58237d8f2dc0b32d6c1a644aa2418aebc476eea3dcf5b4f421f";
+    const RESPONSE_MAC: &str = "12f71b1cff6a3ab10980056410e6e1c0ca7f540dab7f3310669bda530db24dde";
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn tsig() -> Tsig {
+        Tsig {
+            key: "ddns-key.".to_string(),
+            algorithm: Algorithm::HmacSha256,
+            secret: b"secretsecretsecretsecretsecret12".to_vec(),
+        }
+    }
+
+    fn request() -> Vec<u8> {
+        let rr = Rr::add("Host.Example.com", TYPE_A, 60, vec![192, 0, 2, 1]);
+        let mut msg = update("example.com.", &[rr]);
+        msg[0..2].copy_from_slice(&[0x12, 0x34]);
+        msg
+    }
+
+    fn response(mac: &[u8]) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.u16(0x1234);
+        w.u16(FLAG_QR | OPCODE_UPDATE);
+        w.u16(1);
+        w.u16(0);
+        w.u16(0);
+        w.u16(1);
+        w.name("example.com");
+        w.u16(TYPE_SOA);
+        w.u16(CLASS_IN);
+        let mut rdata = Writer::default();
+        rdata.name("hmac-sha256");
+        rdata.u16(0);
+        rdata.u32(TIME as u32);
+        rdata.u16(FUDGE);
+        rdata.u16(mac.len() as u16);
+        rdata.0.extend_from_slice(mac);
+        rdata.u16(0x1234);
+        rdata.u16(0);
+        rdata.u16(0);
+        w.rr(&Rr {
+            name: "ddns-key".to_string(),
+            kind: TYPE_TSIG,
+            class: CLASS_ANY,
+            ttl: 0,
+            rdata: rdata.0,
+        });
+        w.0
+    }
+
+    #[test]
+    fn tsig_vector() {
+        let tsig = tsig();
+        let mac = tsig.mac(&[&request(), &tsig.variables(TIME, FUDGE, 0, &[])]);
+        assert_eq!(mac, hex(REQUEST_MAC));
+
+        let request_mac = hex(REQUEST_MAC);
+        let response = response(&hex(RESPONSE_MAC));
+        assert_eq!(tsig.verify_at(&response, &request_mac, TIME).unwrap(), 0);
+        let late = TIME + FUDGE as u64;
+        assert_eq!(tsig.verify_at(&response, &request_mac, late).unwrap(), 0);
+
+        let mut forged = response.clone();
+        forged[3] |= 5; // REFUSED
+        assert!(tsig.verify_at(&forged, &request_mac, TIME).is_err());
+        assert!(tsig.verify_at(&response, &[0; 32], TIME).is_err());
+        // replayed later, or with a clock too far off
+        assert!(tsig.verify_at(&response, &request_mac, late + 1).is_err());
+        assert!(tsig.verify(&response, &request_mac).is_err());
+    }
+
+    #[test]
+    fn tsig_sign() {
+        let tsig = tsig();
+        let mut msg = request();
+        let unsigned = msg.clone();
+        let mac = tsig.sign(&mut msg);
+        assert_eq!(msg[..10], unsigned[..10]);
+        assert_eq!(msg[10..12], [0, 1]);
+
+        // the appended record carries the returned mac over the
+        // unsigned message and its own time
+        let mut r = Reader {
+            buf: &msg,
+            pos: unsigned.len(),
+        };
+        assert_eq!(r.name().unwrap(), "ddns-key");
+        assert_eq!(r.u16().unwrap(), TYPE_TSIG);
+        r.bytes(8).unwrap();
+        assert_eq!(r.name().unwrap(), "hmac-sha256");
+        let time = (r.u16().unwrap() as u64) << 32 | r.u32().unwrap() as u64;
+        assert_eq!(r.u16().unwrap(), FUDGE);
+        let len = r.u16().unwrap() as usize;
+        assert_eq!(r.bytes(len).unwrap(), mac);
+        let variables = tsig.variables(time, FUDGE, 0, &[]);
+        assert_eq!(tsig.mac(&[&unsigned, &variables]), mac);
+    }
+
+    #[test]
+    fn names() {
+        let mut buf = vec![7];
+        buf.extend_from_slice(b"Example");
+        buf.push(3);
+        buf.extend_from_slice(b"com");
+        buf.push(0);
+        buf.push(3);
+        buf.extend_from_slice(b"www");
+        buf.extend_from_slice(&[0xc0, 0x00, 0xff]);
+
+        let mut r = Reader { buf: &buf, pos: 0 };
+        assert_eq!(r.name().unwrap(), "example.com");
+        assert_eq!(r.pos, 13);
+        assert_eq!(r.name().unwrap(), "www.example.com");
+        assert_eq!(r.pos, 19);
+
+        let mut r = Reader {
+            buf: &[0xc0, 0x00],
+            pos: 0,
+        };
+        assert!(r.name().is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        for buf in [&[7, b'e', b'x'][..], &[3, b'c', b'o', b'm'], &[0xc0], &[]] {
+            let mut r = Reader { buf, pos: 0 };
+            assert!(r.name().is_err());
+        }
+        let mut r = Reader {
+            buf: &[0, 1, 0],
+            pos: 0,
+        };
+        assert_eq!(r.u16().unwrap(), 1);
+        assert!(r.u16().is_err());
+        assert!(r.u32().is_err());
+
+        let mut response = response(&hex(RESPONSE_MAC));
+        response.truncate(response.len() - 8);
+        assert!(tsig()
+            .verify_at(&response, &hex(REQUEST_MAC), TIME)
+            .is_err());
+    }
+
+    // a udp server that only sets TC, and a tcp server with the answer
+    #[test]
+    fn tcp_fallback() {
+        let (udp, tcp) = loop {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            if let Ok(tcp) = TcpListener::bind(udp.local_addr().unwrap()) {
+                break (udp, tcp);
+            }
+        };
+        let server = udp.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (len, peer) = udp.recv_from(&mut buf).unwrap();
+            let mut response = buf[..len].to_vec();
+            response[2..4].copy_from_slice(&(FLAG_QR | FLAG_TC).to_be_bytes());
+            udp.send_to(&response, peer).unwrap();
+        });
+        thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut request = vec![0; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut request).unwrap();
+            let mut w = Writer(request);
+            w.0[2..4].copy_from_slice(&FLAG_QR.to_be_bytes());
+            w.0[6..8].copy_from_slice(&1u16.to_be_bytes());
+            w.u16(0xc00c); // pointer to the question
+            w.u16(TYPE_A);
+            w.u16(CLASS_IN);
+            w.u32(60);
+            w.u16(4);
+            w.0.extend_from_slice(&[192, 0, 2, 1]);
+            stream.write_all(&(w.0.len() as u16).to_be_bytes()).unwrap();
+            stream.write_all(&w.0).unwrap();
+        });
+
+        let answers = query(&server, "host.example.com", TYPE_A, false).unwrap();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].kind, TYPE_A);
+        assert_eq!(answers[0].data, "192.0.2.1");
+    }
+}
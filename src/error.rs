@@ -1,11 +1,31 @@
 use std::fmt::{Display, Formatter, Result};
 
+use crate::requests;
+
 #[derive(Debug)]
-pub struct Error(pub String);
+pub enum Error {
+    // non-2xx response with its body
+    Status(u16, String),
+    // connect, read or overall deadline of a request
+    Timeout(String),
+    Other(String),
+}
+
+impl Error {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Status(code, _) => requests::is_transient(*code),
+            Error::Timeout(_) | Error::Other(_) => true,
+        }
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        write!(f, "{}", self.0)
+        match self {
+            Error::Status(code, msg) => write!(f, "code: {code}, msg: {msg}"),
+            Error::Timeout(msg) | Error::Other(msg) => write!(f, "{msg}"),
+        }
     }
 }
 
@@ -16,6 +36,6 @@ impl serde::ser::Error for Error {
     where
         T: Display,
     {
-        Self(msg.to_string())
+        Self::Other(msg.to_string())
     }
 }
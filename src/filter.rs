@@ -0,0 +1,205 @@
+use std::fmt::{self, Display};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use once_cell::sync::Lazy;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::config::CONFIG;
+
+static REJECTED_V4: AtomicUsize = AtomicUsize::new(0);
+static REJECTED_V6: AtomicUsize = AtomicUsize::new(0);
+
+// special-purpose ranges that must never end up in a public record
+static RESERVED_V4: Lazy<Vec<Cidr>> = Lazy::new(|| {
+    parse_all(&[
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.0.0.0/24",
+        "192.0.2.0/24",
+        "192.168.0.0/16",
+        "198.18.0.0/15",
+        "198.51.100.0/24",
+        "203.0.113.0/24",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+    ])
+});
+static RESERVED_V6: Lazy<Vec<Cidr>> = Lazy::new(|| {
+    parse_all(&[
+        "::/128",
+        "::1/128",
+        "::ffff:0:0/96",
+        "64:ff9b:1::/48",
+        "100::/64",
+        "2001:2::/48",
+        "2001:db8::/32",
+        "fc00::/7",
+        "fe80::/10",
+        "fec0::/10",
+        "ff00::/8",
+    ])
+});
+
+fn parse_all(list: &[&str]) -> Vec<Cidr> {
+    list.iter().map(|s| s.parse().unwrap()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl Cidr {
+    /// The `len` bits long network `ip` is part of.
+    pub fn network(ip: IpAddr, len: u8) -> Self {
+        let addr = match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - len.min(32) as u32).unwrap_or(0);
+                IpAddr::from((u32::from(ip) & mask).to_be_bytes())
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - len.min(128) as u32)
+                    .unwrap_or(0);
+                IpAddr::from((u128::from(ip) & mask).to_be_bytes())
+            }
+        };
+        Cidr { addr, len }
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.len as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.len as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid cidr: {s}"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let len = match len {
+            Some(len) => match len.parse::<u8>() {
+                Ok(len) if len <= max => len,
+                _ => return Err(format!("invalid prefix length: {s}")),
+            },
+            None => max,
+        };
+        Ok(Cidr { addr, len })
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum Reason {
+    Denied(Cidr),
+    NotAllowed,
+    NotGlobal,
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Denied(cidr) => write!(f, "matches deny rule {cidr}"),
+            Reason::NotAllowed => write!(f, "not in allow list"),
+            Reason::NotGlobal => write!(f, "not a global address"),
+        }
+    }
+}
+
+/// Deny rules win, an explicit allow rule overrides the built-in
+/// rejection of non-global addresses.
+pub fn check(ip: &IpAddr) -> Result<(), Reason> {
+    let (rules, reserved) = match ip {
+        IpAddr::V4(_) => (&CONFIG.filter.v4, &RESERVED_V4),
+        IpAddr::V6(_) => (&CONFIG.filter.v6, &RESERVED_V6),
+    };
+    if let Some(cidr) = rules.deny.iter().find(|cidr| cidr.contains(ip)) {
+        return Err(Reason::Denied(*cidr));
+    }
+    if !rules.allow.is_empty() {
+        if rules.allow.iter().any(|cidr| cidr.contains(ip)) {
+            return Ok(());
+        }
+        return Err(Reason::NotAllowed);
+    }
+    if CONFIG.filter.global && reserved.iter().any(|cidr| cidr.contains(ip)) {
+        return Err(Reason::NotGlobal);
+    }
+    Ok(())
+}
+
+/// Counts a rejected candidate and returns the total for its family.
+pub fn rejected(ip: &IpAddr) -> usize {
+    let counter = match ip {
+        IpAddr::V4(_) => &REJECTED_V4,
+        IpAddr::V6(_) => &REJECTED_V6,
+    };
+    counter.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network() {
+        let ip: IpAddr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+        assert_eq!(Cidr::network(ip, 56).to_string(), "2001:db8:1::/56");
+        assert_eq!(Cidr::network(ip, 64).to_string(), "2001:db8:1:2::/64");
+        let ip: IpAddr = "192.0.2.77".parse().unwrap();
+        assert_eq!(Cidr::network(ip, 24).to_string(), "192.0.2.0/24");
+        assert_eq!(Cidr::network(ip, 0).to_string(), "0.0.0.0/0");
+    }
+}
@@ -3,18 +3,31 @@ use dotenvy::dotenv;
 #[macro_use]
 extern crate log;
 
+mod acme;
 mod api;
+mod cli;
 mod config;
+mod dns;
 mod error;
+mod filter;
 mod logger;
 mod requests;
+mod rest;
 mod ser;
+mod state;
+#[cfg(test)]
+mod testing;
 mod util;
 
 pub use error::Error;
 pub use requests::CLIENT;
 
 fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        dotenv().ok();
+        return cli::run(&args);
+    }
     launch_info();
     dotenv().ok();
     logger::init_logger();
@@ -0,0 +1,103 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+use crate::requests::{RequestBuilder, Response};
+use crate::{Error, CLIENT};
+
+const REST_URL: &str = "https://dynv6.com/api/v2";
+
+#[derive(Deserialize, Debug)]
+pub struct Zone {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub ipv4address: String,
+    #[serde(default)]
+    pub ipv6prefix: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Record {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    // relative to the zone, empty for the apex
+    pub name: String,
+    pub data: String,
+    #[serde(default)]
+    pub priority: Option<u16>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NewRecord<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'a str,
+    pub name: &'a str,
+    pub data: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u16>,
+}
+
+pub struct Rest {
+    token: &'static str,
+}
+
+impl Rest {
+    pub fn new() -> Self {
+        Rest {
+            token: &CONFIG.token,
+        }
+    }
+
+    fn check(res: Response) -> Result<Response, Error> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+        let text = res.text().unwrap_or_default();
+        Err(Error::Status(status.as_u16(), text.trim().to_string()))
+    }
+
+    fn call<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T, Error> {
+        Self::check(req.bearer_auth(self.token).send()?)?.json()
+    }
+
+    pub fn zones(&self) -> Result<Vec<Zone>, Error> {
+        self.call(CLIENT.get(&format!("{REST_URL}/zones")))
+    }
+
+    pub fn zone_by_name(&self, name: &str) -> Result<Zone, Error> {
+        self.call(CLIENT.get(&format!("{REST_URL}/zones/by-name/{name}")))
+    }
+
+    pub fn records(&self, zone: u64) -> Result<Vec<Record>, Error> {
+        self.call(CLIENT.get(&format!("{REST_URL}/zones/{zone}/records")))
+    }
+
+    pub fn record(&self, zone: u64, id: u64) -> Result<Record, Error> {
+        self.call(CLIENT.get(&format!("{REST_URL}/zones/{zone}/records/{id}")))
+    }
+
+    pub fn create_record(&self, zone: u64, record: &NewRecord) -> Result<Record, Error> {
+        let req = CLIENT
+            .post(&format!("{REST_URL}/zones/{zone}/records"))
+            .bearer_auth(self.token);
+        Self::check(req.send_json(record)?)?.json()
+    }
+
+    pub fn update_record(&self, zone: u64, id: u64, record: &NewRecord) -> Result<Record, Error> {
+        let req = CLIENT
+            .patch(&format!("{REST_URL}/zones/{zone}/records/{id}"))
+            .bearer_auth(self.token);
+        Self::check(req.send_json(record)?)?.json()
+    }
+
+    pub fn delete_record(&self, zone: u64, id: u64) -> Result<(), Error> {
+        let req = CLIENT
+            .delete(&format!("{REST_URL}/zones/{zone}/records/{id}"))
+            .bearer_auth(self.token);
+        Self::check(req.send()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+pub fn load<T: FromStr>(file: &str) -> Option<T> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(err) => {
+            warn!("{file}: {err}, treating as unknown");
+            return None;
+        }
+    };
+    let content = content.trim();
+    if content.is_empty() {
+        return None;
+    }
+    match content.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("{file}: invalid content {content:?}, treating as unknown");
+            None
+        }
+    }
+}
+
+pub fn save<T: Display>(file: &str, value: &T) {
+    if let Err(err) = fs::write(file, value.to_string()) {
+        error!("{file}: {err}");
+    }
+}
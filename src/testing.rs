@@ -0,0 +1,50 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Once;
+use std::thread;
+
+static INIT: Once = Once::new();
+
+/// Sets what `CONFIG` can't default, call before anything touches it.
+pub fn init() {
+    INIT.call_once(|| std::env::set_var("DYNV6_TOKEN", "test"));
+}
+
+/// Answers one request on a local port with `status` and `body`.
+/// Returns the base url and the request line and headers it got.
+pub fn serve(status: u16, body: &str) -> (String, Receiver<String>) {
+    init();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let body = body.to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            request.push_str(&line);
+        }
+        let mut content = vec![0; length];
+        reader.read_exact(&mut content).unwrap();
+        request.push_str(&String::from_utf8_lossy(&content));
+        tx.send(request).ok();
+        let response = format!(
+            "HTTP/1.1 {status} STATUS\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+    });
+    (url, rx)
+}
@@ -1,64 +1,329 @@
-use std::net::IpAddr;
+use std::fmt::Display;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use local_ip_address::list_afinet_netifas;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
-use crate::config::CONFIG;
-use crate::{Error, CLIENT};
+use crate::config::{Echo, Format, CONFIG};
+use crate::filter;
+use crate::requests::ECHO_CLIENT;
+use crate::Error;
 
-const IPV4_URL: &'static str = "https://api4.my-ip.io/ip";
+static COMMAND_REGEX: Lazy<Option<Regex>> = Lazy::new(|| {
+    CONFIG
+        .command
+        .regex
+        .as_ref()
+        .map(|regex| match Regex::new(regex) {
+            Ok(regex) => regex,
+            Err(err) => panic!("invalid command regex: {err}"),
+        })
+});
 
-pub fn ipv6() -> Option<IpAddr> {
+fn accept(ip: &IpAddr) -> bool {
+    match filter::check(ip) {
+        Ok(()) => true,
+        Err(reason) => {
+            let count = filter::rejected(ip);
+            warn!("rejected {ip}: {reason} ({count} rejected so far)");
+            false
+        }
+    }
+}
+
+// one of several addresses, others may still be accepted, so the
+// rejection is only logged at debug level
+fn candidate(ip: &IpAddr) -> bool {
+    match filter::check(ip) {
+        Ok(()) => true,
+        Err(reason) => {
+            let count = filter::rejected(ip);
+            debug!("rejected {ip}: {reason} ({count} rejected so far)");
+            false
+        }
+    }
+}
+
+fn run_command(cmd: &str) -> Result<String, Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::Other(format!("`{cmd}`: {err}")))?;
+    // drain the pipes in the background so a chatty command can't block on a full pipe
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let stdout = thread::spawn(move || {
+        let mut buf = String::new();
+        stdout.read_to_string(&mut buf).ok();
+        buf
+    });
+    let stderr = thread::spawn(move || {
+        let mut buf = String::new();
+        stderr.read_to_string(&mut buf).ok();
+        buf
+    });
+    let deadline = Instant::now() + Duration::from_secs_f64(CONFIG.command.timeout);
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(Error::Other(format!(
+                    "`{cmd}` timed out after {}s",
+                    CONFIG.command.timeout
+                )));
+            }
+            Err(err) => return Err(Error::Other(format!("`{cmd}`: {err}"))),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.trim();
+        if stderr.is_empty() {
+            return Err(Error::Other(format!("`{cmd}` {status}")));
+        }
+        return Err(Error::Other(format!("`{cmd}` {status}: {stderr}")));
+    }
+    Ok(stdout)
+}
+
+// every match that parses, using capture group 1 when the pattern has one
+fn regex_addrs<'a, T: FromStr>(regex: &'a Regex, text: &'a str) -> impl Iterator<Item = T> + 'a {
+    regex.captures_iter(text).filter_map(|caps| {
+        caps.get(1)
+            .or_else(|| caps.get(0))
+            .and_then(|m| m.as_str().trim().parse().ok())
+    })
+}
+
+fn addrs<T: FromStr>(text: &str) -> Vec<T> {
+    match COMMAND_REGEX.as_ref() {
+        Some(regex) => regex_addrs(regex, text).collect(),
+        None => text
+            .split(|c: char| !(c.is_ascii_hexdigit() || c == ':' || c == '.'))
+            .filter_map(|s| s.parse().ok())
+            .collect(),
+    }
+}
+
+// vendor tools often list ula or link-local addresses before the
+// global one, so the first acceptable address is taken
+fn command<T: FromStr + Copy + Into<IpAddr>>(cmd: &str) -> Option<T> {
+    match run_command(cmd) {
+        Ok(stdout) => {
+            let addrs: Vec<T> = addrs(&stdout);
+            if addrs.is_empty() {
+                error!("no address found in output of `{cmd}`: {}", stdout.trim());
+                return None;
+            }
+            let ip = addrs.into_iter().find(|ip| candidate(&(*ip).into()));
+            if ip.is_none() {
+                warn!("no acceptable address in output of `{cmd}`");
+            }
+            ip
+        }
+        Err(err) => {
+            error!("{err}");
+            None
+        }
+    }
+}
+
+pub fn ipv6() -> Option<Ipv6Addr> {
+    if let Some(cmd) = &CONFIG.command.v6 {
+        return command(cmd);
+    }
     let ifas = list_afinet_netifas().unwrap();
-    #[cfg(not(target_os = "macos"))]
-    {
-        for (name, ip) in ifas.iter() {
-            if name == &CONFIG.interface {
-                if let IpAddr::V6(v6) = ip {
-                    // ipv6 link-local // IpAddr is_unicast_link_local
-                    if (v6.segments()[0] & 0xffc0) != 0xfe80 {
-                        return Some(*ip);
-                    }
+    let mut ipv6_list = vec![];
+    for (name, ip) in ifas.iter() {
+        if name == &CONFIG.interface {
+            if let IpAddr::V6(v6) = ip {
+                // ipv6 link-local // IpAddr is_unicast_link_local
+                if (v6.segments()[0] & 0xffc0) != 0xfe80 {
+                    ipv6_list.push(*v6);
                 }
             }
         }
     }
+    // an interface usually carries ula addresses next to the global one
+    let candidates = ipv6_list.len();
+    ipv6_list.retain(|ip| candidate(&IpAddr::V6(*ip)));
+    if ipv6_list.is_empty() {
+        if candidates > 0 {
+            warn!("no acceptable ipv6 address on {}", CONFIG.interface);
+        }
+        return None;
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Some(ipv6_list[0])
+    }
     #[cfg(target_os = "macos")]
     {
-        let mut ipv6_list = vec![];
-        for (name, ip) in ifas.iter() {
-            if name == &CONFIG.interface {
-                if let IpAddr::V6(v6) = ip {
-                    // ipv6 link-local // IpAddr is_unicast_link_local
-                    if (v6.segments()[0] & 0xffc0) != 0xfe80 {
-                        ipv6_list.push(ip);
-                    }
-                }
-            }
+        if ipv6_list.len() == 1 {
+            return Some(ipv6_list[0]);
         }
-        if !ipv6_list.is_empty() {
-            if ipv6_list.len() == 1 {
-                return Some(*ipv6_list[0]);
-            }
-            return Some(*ipv6_list[ipv6_list.len() - 2]);
+        Some(ipv6_list[ipv6_list.len() - 2])
+    }
+}
+
+// supports `$.field`, `$.nested.field` and `$.list[0].field`
+fn json_path<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    let path = path.trim().trim_start_matches('$');
+    let mut value = value;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (key, indexes) = match segment.find('[') {
+            Some(i) => segment.split_at(i),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            value = value.get(key)?;
+        }
+        for index in indexes.split('[').filter(|s| !s.is_empty()) {
+            value = value.get(index.trim_end_matches(']').parse::<usize>().ok()?)?;
         }
     }
-    None
+    Some(value)
 }
 
-fn fetch_ipv4() -> Result<String, Error> {
-    let res = CLIENT.get(IPV4_URL).send()?;
-    res.text()
+fn parse_echo<T: FromStr>(echo: &Echo, body: &str) -> Result<T, Error> {
+    let invalid = || Error::Other(format!("no address in response: {}", body.trim()));
+    match echo.format {
+        Format::Text => body.trim().parse().map_err(|_| invalid()),
+        Format::Json => {
+            let json: serde_json::Value = serde_json::from_str(body)
+                .map_err(|err| Error::Other(format!("invalid json: {err}")))?;
+            let value = json_path(&json, &echo.path)
+                .ok_or_else(|| Error::Other(format!("{} not found in response", echo.path)))?;
+            value
+                .as_str()
+                .and_then(|s| s.trim().parse().ok())
+                .ok_or_else(|| Error::Other(format!("{} is not an address: {value}", echo.path)))
+        }
+        Format::Regex => {
+            let pattern = echo.regex.as_deref().unwrap_or_default();
+            let regex =
+                Regex::new(pattern).map_err(|err| Error::Other(format!("invalid regex: {err}")))?;
+            let ip = regex_addrs(&regex, body).next();
+            ip.ok_or_else(invalid)
+        }
+    }
+}
+
+fn fetch_ipv4(echo: &Echo) -> Result<Ipv4Addr, Error> {
+    let res = ECHO_CLIENT.get(&echo.url).send()?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(Error::Other(format!("code: {status}")));
+    }
+    parse_echo(echo, &res.text()?)
 }
 
-pub fn ipv4() -> Option<IpAddr> {
-    match fetch_ipv4() {
-        Ok(ip_str) => {
-            if let Ok(ip) = ip_str.parse::<IpAddr>() {
-                return Some(ip);
+pub fn ipv4() -> Option<Ipv4Addr> {
+    if let Some(cmd) = &CONFIG.command.v4 {
+        return command(cmd);
+    }
+    // providers are tried in order until one returns an acceptable address
+    for echo in CONFIG.echo.iter() {
+        match fetch_ipv4(echo) {
+            Ok(ip) => {
+                if accept(&IpAddr::V4(ip)) {
+                    return Some(ip);
+                }
             }
-            error!("{ip_str}");
+            Err(err) => error!("{}: {err}", echo.url),
         }
-        Err(err) => error!("{err}"),
     }
     None
 }
+
+pub fn or_unknown<T: Display>(ip: &Option<T>) -> String {
+    match ip {
+        Some(ip) => ip.to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn command_skips_rejected() {
+        testing::init();
+        let cmd = "echo fe80::1 fd00::1 2606:4700::1111 2606:4700::1001";
+        assert_eq!(command::<Ipv6Addr>(cmd), "2606:4700::1111".parse().ok());
+        assert_eq!(command::<Ipv6Addr>("echo fe80::1 fd00::1"), None);
+        assert_eq!(
+            command::<Ipv4Addr>("echo 10.0.0.2 1.1.1.1"),
+            "1.1.1.1".parse().ok()
+        );
+    }
+
+    const IP: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    fn echo(format: Format, path: &str, regex: Option<&str>) -> Echo {
+        Echo {
+            url: String::new(),
+            format,
+            path: path.to_string(),
+            regex: regex.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn json_paths() {
+        let json = serde_json::json!({
+            "ip": "192.0.2.1",
+            "data": {"client": {"addr": "192.0.2.2"}},
+            "list": [{"ip": "192.0.2.3"}, [1, 2]],
+        });
+        assert_eq!(json_path(&json, "$.ip").unwrap(), "192.0.2.1");
+        assert_eq!(
+            json_path(&json, " $.data.client.addr").unwrap(),
+            "192.0.2.2"
+        );
+        assert_eq!(json_path(&json, "$.list[0].ip").unwrap(), "192.0.2.3");
+        assert_eq!(json_path(&json, "$.list[1][1]").unwrap(), 2);
+        assert_eq!(json_path(&json, "$").unwrap(), &json);
+        assert!(json_path(&json, "$.missing").is_none());
+        assert!(json_path(&json, "$.list[5]").is_none());
+        assert!(json_path(&json, "$.list[x]").is_none());
+    }
+
+    #[test]
+    fn echo_formats() {
+        let text = echo(Format::Text, "$.ip", None);
+        assert_eq!(parse_echo::<Ipv4Addr>(&text, " 192.0.2.1\n").unwrap(), IP);
+        assert!(parse_echo::<Ipv4Addr>(&text, "<html>").is_err());
+
+        let json = echo(Format::Json, "$.ip", None);
+        let ip: Ipv4Addr = parse_echo(&json, r#"{"ip": "192.0.2.1"}"#).unwrap();
+        assert_eq!(ip, IP);
+        // a number or a missing field is no address
+        assert!(parse_echo::<Ipv4Addr>(&json, r#"{"ip": 3221225985}"#).is_err());
+        assert!(parse_echo::<Ipv4Addr>(&json, r#"{"addr": "192.0.2.1"}"#).is_err());
+        assert!(parse_echo::<Ipv4Addr>(&json, r#"{"ip": "192.0.2.1""#).is_err());
+
+        let regex = echo(Format::Regex, "$.ip", Some(r"Current IP: ([\d.]+)"));
+        let body = "Version 1.2.3.4<br>Current IP: 192.0.2.1";
+        assert_eq!(parse_echo::<Ipv4Addr>(&regex, body).unwrap(), IP);
+        assert!(parse_echo::<Ipv4Addr>(&regex, "Current IP: none").is_err());
+        let invalid = echo(Format::Regex, "$.ip", Some("("));
+        assert!(parse_echo::<Ipv4Addr>(&invalid, body).is_err());
+    }
+}